@@ -5,10 +5,7 @@ use crate::utils::process_from_input;
 use crate::Format;
 
 pub fn encode(input: &str, format: Format) -> anyhow::Result<()> {
-    let input_str = match process_from_input(input) {
-        Ok(s) => s,
-        Err(e) => return Err(e),
-    };
+    let input_str = process_from_input(input)?;
 
     let encoded = match format {
         Format::Standard => STANDARD.encode(input_str.as_bytes()),
@@ -19,19 +16,13 @@ pub fn encode(input: &str, format: Format) -> anyhow::Result<()> {
 }
 
 pub fn decode(input: &str, format: Format) -> anyhow::Result<()> {
-    let input_str = match process_from_input(input) {
-        Ok(s) => s,
-        Err(e) => return Err(e),
-    };
+    let input_str = process_from_input(input)?;
 
     let decoded = match format {
         Format::Standard => STANDARD.decode(input_str.as_bytes()),
         Format::UrlSafe => URL_SAFE_NO_PAD.decode(input_str.as_bytes()),
     };
-    let decoded = match decoded {
-        Ok(d) => d,
-        Err(e) => return Err(e.into()),
-    };
+    let decoded = decoded?;
     println!("{}", String::from_utf8_lossy(&decoded));
     Ok(())
 }
@@ -0,0 +1,199 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde::de::IgnoredAny;
+use serde_json::{Map, Value};
+
+use crate::utils::process_from_input;
+use crate::{format_documents, parse_documents, ConvertOpts, FileFormat};
+
+/// Convert a file, or stdin, from one structured format to another.
+pub fn convert_file(opts: ConvertOpts) -> Result<()> {
+    let from = opts
+        .from
+        .or_else(|| format_of(&opts.input))
+        .ok_or_else(|| anyhow!("Cannot tell the input format, use --from"))?;
+    let to = opts
+        .to
+        .or_else(|| format_of(opts.output.as_deref()?))
+        .ok_or_else(|| anyhow!("Cannot tell the output format, use --to"))?;
+    let content = process_from_input(&opts.input)?;
+    let mut output = convert(&content, from, to, !opts.compact, opts.sort_keys)?;
+    if !output.ends_with('\n') {
+        output.push('\n');
+    }
+    match opts.output.as_deref() {
+        None | Some("-") => print!("{}", output),
+        Some(path) => fs::write(path, output)?,
+    }
+    Ok(())
+}
+
+fn convert(
+    content: &str,
+    from: FileFormat,
+    to: FileFormat,
+    pretty: bool,
+    sort: bool,
+) -> Result<String> {
+    if let (FileFormat::Json, FileFormat::Json, false) = (from, to, sort) {
+        // reformats the text, keeping numbers and duplicate keys as written,
+        // but jsonxf passes broken JSON through
+        for value in serde_json::Deserializer::from_str(content).into_iter::<IgnoredAny>() {
+            value?;
+        }
+        let json = match pretty {
+            true => jsonxf::pretty_print(content),
+            false => jsonxf::minimize(content),
+        };
+        return json.map_err(|e| anyhow!(e));
+    }
+    let mut documents = parse_documents(content, from)?;
+    if sort {
+        documents = documents.into_iter().map(sort_keys).collect();
+    }
+    format_documents(&documents, to, pretty)
+}
+
+fn format_of(path: &str) -> Option<FileFormat> {
+    Path::new(path).extension()?.to_str()?.parse().ok()
+}
+
+fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sort_keys(value)))
+                    .collect::<Map<_, _>>(),
+            )
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(sort_keys).collect()),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    const YAML: &str = "b: 1\na: {d: [1, 2], c: null}\n---\nb: 2\n";
+
+    #[test]
+    fn test_convert() {
+        let json = |pretty, sort| convert(YAML, FileFormat::Yaml, FileFormat::Json, pretty, sort);
+        assert_eq!(
+            json(false, false).unwrap(),
+            r#"[{"b":1,"a":{"d":[1,2],"c":null}},{"b":2}]"#
+        );
+        assert_eq!(
+            json(false, true).unwrap(),
+            r#"[{"a":{"c":null,"d":[1,2]},"b":1},{"b":2}]"#
+        );
+        assert!(json(true, false)
+            .unwrap()
+            .starts_with("[\n  {\n    \"b\": 1,"));
+
+        assert_eq!(
+            convert(
+                "{\"b\": 1.50, \"a\": 2}",
+                FileFormat::Json,
+                FileFormat::Json,
+                false,
+                false
+            )
+            .unwrap(),
+            "{\"b\":1.50,\"a\":2}"
+        );
+        assert_eq!(
+            convert(
+                "{\"b\": 1, \"a\": 2}",
+                FileFormat::Json,
+                FileFormat::Json,
+                false,
+                true
+            )
+            .unwrap(),
+            "{\"a\":2,\"b\":1}"
+        );
+        assert_eq!(
+            convert(YAML, FileFormat::Yaml, FileFormat::Ndjson, false, false).unwrap(),
+            "{\"b\":1,\"a\":{\"d\":[1,2],\"c\":null}}\n{\"b\":2}\n"
+        );
+        assert_eq!(
+            convert(YAML, FileFormat::Yaml, FileFormat::Csv, true, false).unwrap(),
+            "b,a.d,a.c\n1,1|2,\n2,,\n"
+        );
+        assert_eq!(
+            convert(
+                "[x]\ny = 1\n",
+                FileFormat::Toml,
+                FileFormat::Yaml,
+                true,
+                false
+            )
+            .unwrap(),
+            "x:\n  y: 1\n"
+        );
+        assert_eq!(
+            convert(
+                "{\"x\": {\"y\": 1}}",
+                FileFormat::Json,
+                FileFormat::Toml,
+                true,
+                false
+            )
+            .unwrap(),
+            "[x]\ny = 1\n"
+        );
+        assert_eq!(
+            convert("a,b\n1,x\n", FileFormat::Csv, FileFormat::Yaml, true, false).unwrap(),
+            "- a: 1\n  b: x\n"
+        );
+        assert_eq!(
+            convert(
+                "a: 1\n---\na: 2\n",
+                FileFormat::Yaml,
+                FileFormat::Yaml,
+                true,
+                false
+            )
+            .unwrap(),
+            "a: 1\n---\na: 2\n"
+        );
+        assert!(convert("{", FileFormat::Json, FileFormat::Yaml, true, false).is_err());
+        for broken in ["{\"a\": [1, 2", "{\"a\": tru}", "[1] x"] {
+            for pretty in [true, false] {
+                let json = convert(broken, FileFormat::Json, FileFormat::Json, pretty, false);
+                assert!(json.is_err(), "{}", broken);
+            }
+        }
+    }
+
+    #[test]
+    fn test_convert_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.yml");
+        let output = dir.path().join("output.json");
+        fs::write(&input, YAML).unwrap();
+        let (input, output) = (input.to_str().unwrap(), output.to_str().unwrap());
+        let opts = ConvertOpts::try_parse_from(["convert", "-i", input, "-o", output, "--compact"]);
+        convert_file(opts.unwrap()).unwrap();
+        assert_eq!(
+            fs::read_to_string(output).unwrap(),
+            "[{\"b\":1,\"a\":{\"d\":[1,2],\"c\":null}},{\"b\":2}]\n"
+        );
+
+        // stdin has no extension to tell the format by
+        let opts = ConvertOpts::try_parse_from(["convert", "--to", "json"]).unwrap();
+        assert!(convert_file(opts).is_err());
+        let opts = ConvertOpts::try_parse_from(["convert", "-i", input]).unwrap();
+        assert!(convert_file(opts).is_err());
+    }
+}
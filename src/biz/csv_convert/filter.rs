@@ -0,0 +1,326 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use super::format::cell;
+
+/// A `--where` condition on a row, e.g.
+/// `Age >= 30 and (Position == 'Goalkeeper' or not Nationality == 'Italy')`.
+///
+/// Operands are column names, quoted with backticks when they hold spaces,
+/// or literals: numbers, 'strings', "strings", true, false and null. The
+/// operators are `==` (or `=`), `!=`, `<`, `<=`, `>`, `>=` and `contains`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowFilter {
+    And(Box<RowFilter>, Box<RowFilter>),
+    Or(Box<RowFilter>, Box<RowFilter>),
+    Not(Box<RowFilter>),
+    Compare(Operand, Op, Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Column(String),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Op(Op),
+    Word(String),
+    Column(String),
+    Literal(Value),
+}
+
+impl FromStr for RowFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let filter = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(filter),
+            Some(token) => Err(format!("Unexpected {:?} in --where", token)),
+        }
+    }
+}
+
+impl RowFilter {
+    /// The columns the condition refers to.
+    pub fn columns(&self) -> Vec<&str> {
+        match self {
+            RowFilter::And(a, b) | RowFilter::Or(a, b) => {
+                let mut columns = a.columns();
+                columns.extend(b.columns());
+                columns
+            }
+            RowFilter::Not(a) => a.columns(),
+            RowFilter::Compare(a, _, b) => [a, b]
+                .into_iter()
+                .filter_map(|operand| match operand {
+                    Operand::Column(name) => Some(name.as_str()),
+                    Operand::Literal(_) => None,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn matches(&self, row: &Value) -> bool {
+        match self {
+            RowFilter::And(a, b) => a.matches(row) && b.matches(row),
+            RowFilter::Or(a, b) => a.matches(row) || b.matches(row),
+            RowFilter::Not(a) => !a.matches(row),
+            RowFilter::Compare(a, op, b) => {
+                let (a, b) = (a.value(row), b.value(row));
+                let ordering = compare(a, b);
+                match op {
+                    Op::Eq => ordering == Some(Ordering::Equal),
+                    Op::Ne => ordering != Some(Ordering::Equal),
+                    Op::Lt => ordering == Some(Ordering::Less),
+                    Op::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                    Op::Gt => ordering == Some(Ordering::Greater),
+                    Op::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+                    Op::Contains => !a.is_null() && !b.is_null() && cell(a).contains(&cell(b)),
+                }
+            }
+        }
+    }
+}
+
+impl Operand {
+    fn value<'a>(&'a self, row: &'a Value) -> &'a Value {
+        match self {
+            Operand::Column(name) => row.get(name).unwrap_or(&Value::Null),
+            Operand::Literal(value) => value,
+        }
+    }
+}
+
+/// Numbers by value, also against strings that parse as numbers, e.g. cells
+/// read with `--no-infer`; strings and booleans by themselves, other mixes by
+/// their text; null equals only null and is not ordered.
+pub(super) fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    if a.is_number() || b.is_number() {
+        if let (Some(a), Some(b)) = (number(a), number(b)) {
+            return a.partial_cmp(&b);
+        }
+    }
+    match (a, b) {
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (a, b) => Some(cell(a).cmp(&cell(b))),
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '\'' | '"' | '`' => {
+                chars.next();
+                let text: String = chars
+                    .by_ref()
+                    .map(|(_, c)| c)
+                    .take_while(|&ch| ch != c)
+                    .collect();
+                if !s[start + 1..].contains(c) {
+                    return Err(format!("Unclosed {} in --where", c));
+                }
+                tokens.push(match c {
+                    '`' => Token::Column(text),
+                    _ => Token::Literal(Value::String(text)),
+                });
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let eq = chars.next_if(|&(_, c)| c == '=').is_some();
+                let op = match (c, eq) {
+                    ('=', _) => Op::Eq,
+                    ('!', true) => Op::Ne,
+                    ('<', false) => Op::Lt,
+                    ('<', true) => Op::Le,
+                    ('>', false) => Op::Gt,
+                    ('>', true) => Op::Ge,
+                    _ => return Err("Expected != in --where".to_string()),
+                };
+                tokens.push(Token::Op(op));
+            }
+            _ => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_whitespace() || "()'\"`=!<>".contains(c) {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                let word = &s[start..end];
+                let token = match word.to_lowercase().as_str() {
+                    "contains" => Token::Op(Op::Contains),
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" => Token::Literal(Value::Null),
+                    _ => match word.parse::<f64>() {
+                        Ok(n) if word.starts_with(|c: char| c.is_ascii_digit() || c == '-') => {
+                            Token::Literal(
+                                serde_json::Number::from_f64(n).map_or(Value::Null, Value::Number),
+                            )
+                        }
+                        _ => Token::Word(word.to_string()),
+                    },
+                };
+                tokens.push(token);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<RowFilter, String> {
+        let mut filter = self.and()?;
+        while self.keyword("or") {
+            filter = RowFilter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<RowFilter, String> {
+        let mut filter = self.not()?;
+        while self.keyword("and") {
+            filter = RowFilter::And(Box::new(filter), Box::new(self.not()?));
+        }
+        Ok(filter)
+    }
+
+    fn not(&mut self) -> Result<RowFilter, String> {
+        if self.keyword("not") {
+            return Ok(RowFilter::Not(Box::new(self.not()?)));
+        }
+        if self.tokens.get(self.pos) == Some(&Token::Open) {
+            self.pos += 1;
+            let filter = self.or()?;
+            if self.tokens.get(self.pos) != Some(&Token::Close) {
+                return Err("Missing ) in --where".to_string());
+            }
+            self.pos += 1;
+            return Ok(filter);
+        }
+        let left = self.operand()?;
+        let op = match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => *op,
+            _ => return Err("Expected an operator in --where".to_string()),
+        };
+        self.pos += 1;
+        Ok(RowFilter::Compare(left, op, self.operand()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let operand = match self.tokens.get(self.pos) {
+            Some(Token::Word(name) | Token::Column(name)) => Operand::Column(name.clone()),
+            Some(Token::Literal(value)) => Operand::Literal(value.clone()),
+            _ => return Err("Expected a column or a value in --where".to_string()),
+        };
+        self.pos += 1;
+        Ok(operand)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn matches(filter: &str, row: &Value) -> bool {
+        filter.parse::<RowFilter>().unwrap().matches(row)
+    }
+
+    #[test]
+    fn test_row_filter() {
+        let row = json!({"Name": "Ada", "Age": 36, "Kit Number": 7, "Team": null, "Active": true});
+        assert!(matches("Age > 30", &row));
+        assert!(matches("Age>=36 and Age<=36", &row));
+        assert!(!matches("Age < 30", &row));
+        assert!(matches("Name = 'Ada'", &row));
+        assert!(matches("Name == \"Ada\" and not Age != 36", &row));
+        assert!(matches("`Kit Number` = 7", &row));
+        assert!(matches("Name contains 'd'", &row));
+        assert!(matches("Team == null and Active = true", &row));
+        assert!(!matches("Team > 1", &row));
+        assert!(matches("Age < 30 or (Name = 'Ada' AND Age > 35)", &row));
+        assert!(matches("Age = -1 or Age = 36.0", &row));
+        assert!(!matches("Missing == 1", &row));
+        // cells kept as text still compare as numbers
+        let row = json!({"Age": "100", "Name": "40"});
+        assert!(matches("Age > 40", &row));
+        assert!(matches("40 < Age and Age == 100.0", &row));
+        assert!(!matches("Age < 40", &row));
+        assert!(matches("Name = 40 and Name < 'A'", &row));
+
+        let filter: RowFilter = "(a = 1 or b < c) and not `d e` contains 'x'"
+            .parse()
+            .unwrap();
+        assert_eq!(filter.columns(), ["a", "b", "c", "d e"]);
+
+        for bad in [
+            "",
+            "Age >",
+            "Age 30",
+            "(Age > 1",
+            "Age > 1)",
+            "Name = 'Ada",
+            "a ! b",
+        ] {
+            assert!(bad.parse::<RowFilter>().is_err(), "{}", bad);
+        }
+    }
+}
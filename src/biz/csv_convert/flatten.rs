@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use serde_json::Value;
+
+use super::format::{cell, parse_rows};
+use crate::utils::process_from_input;
+use crate::{ArrayMode, ToCsvOpts};
+
+type Row = Vec<(String, String)>;
+
+/// Flatten the rows of a JSON, YAML, TOML or NDJSON file, or stdin, into
+/// CSV. Returns the number of rows written.
+pub fn file2csv(opts: ToCsvOpts) -> Result<usize> {
+    let format = match opts.format {
+        Some(format) => format,
+        None => Path::new(&opts.input)
+            .extension()
+            .and_then(|ext| ext.to_str()?.parse().ok())
+            .ok_or_else(|| anyhow!("Cannot tell the format of {}, use --format", opts.input))?,
+    };
+    let output = opts.output.unwrap_or_else(|| "output.csv".to_string());
+    println!("{} {}", "Output file: ".blue(), output.blue());
+    let content = process_from_input(&opts.input)?;
+    let rows = parse_rows(&content, format)?;
+
+    let rows: Vec<Row> = rows
+        .iter()
+        .flat_map(|row| flatten(row, opts.arrays, &opts.separator))
+        .collect();
+    let csv = write_csv(&rows, opts.delimiter)?;
+    fs::write(output, csv)?;
+    Ok(rows.len())
+}
+
+/// The CSV rows of one value: nested keys are joined with dots, arrays are
+/// joined into one cell or exploded into a row per item.
+pub(super) fn flatten(value: &Value, arrays: ArrayMode, separator: &str) -> Vec<Row> {
+    match value {
+        Value::Object(_) => flatten_into("", value, vec![Row::new()], arrays, separator),
+        other => flatten_into("value", other, vec![Row::new()], arrays, separator),
+    }
+}
+
+fn flatten_into(
+    prefix: &str,
+    value: &Value,
+    rows: Vec<Row>,
+    arrays: ArrayMode,
+    separator: &str,
+) -> Vec<Row> {
+    match value {
+        Value::Object(map) => map.iter().fold(rows, |rows, (key, value)| {
+            let key = match prefix {
+                "" => key.clone(),
+                _ => format!("{}.{}", prefix, key),
+            };
+            flatten_into(&key, value, rows, arrays, separator)
+        }),
+        Value::Array(items) if arrays == ArrayMode::Explode && !items.is_empty() => rows
+            .into_iter()
+            .flat_map(|row| {
+                items.iter().flat_map(move |item| {
+                    flatten_into(prefix, item, vec![row.clone()], arrays, separator)
+                })
+            })
+            .collect(),
+        Value::Array(items) => {
+            let joined = match items.iter().any(|item| item.is_array() || item.is_object()) {
+                // nested structures keep their shape as JSON
+                true => value.to_string(),
+                false => items.iter().map(cell).collect::<Vec<_>>().join(separator),
+            };
+            push_cell(rows, prefix, joined)
+        }
+        scalar => push_cell(rows, prefix, cell(scalar)),
+    }
+}
+
+fn push_cell(mut rows: Vec<Row>, key: &str, cell: String) -> Vec<Row> {
+    for row in &mut rows {
+        row.push((key.to_string(), cell.clone()));
+    }
+    rows
+}
+
+/// Columns are the union of all keys, in the order they first appear.
+pub(super) fn write_csv(rows: &[Row], delimiter: u8) -> Result<String> {
+    let mut headers: Vec<&str> = Vec::new();
+    for row in rows {
+        for (key, _) in row {
+            if !headers.contains(&key.as_str()) {
+                headers.push(key);
+            }
+        }
+    }
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(Vec::new());
+    writer.write_record(&headers)?;
+    for row in rows {
+        writer.write_record(headers.iter().map(|header| {
+            row.iter()
+                .find(|(key, _)| key == header)
+                .map_or("", |(_, cell)| cell.as_str())
+        }))?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn to_csv(rows: &[Value], arrays: ArrayMode) -> String {
+        let rows: Vec<Row> = rows
+            .iter()
+            .flat_map(|row| flatten(row, arrays, "|"))
+            .collect();
+        write_csv(&rows, b',').unwrap()
+    }
+
+    #[test]
+    fn test_flatten() {
+        let rows = [
+            json!({"name": "Ada", "address": {"city": "London", "zip": null}, "tags": ["a", "b"]}),
+            json!({"name": "Bob", "age": 40, "tags": [], "pets": [{"kind": "cat"}]}),
+        ];
+        assert_eq!(
+            to_csv(&rows, ArrayMode::Join),
+            "name,address.city,address.zip,tags,age,pets\n\
+             Ada,London,,a|b,,\n\
+             Bob,,,,40,\"[{\"\"kind\"\":\"\"cat\"\"}]\"\n"
+        );
+        assert_eq!(
+            to_csv(&rows, ArrayMode::Explode),
+            "name,address.city,address.zip,tags,age,pets.kind\n\
+             Ada,London,,a,,\n\
+             Ada,London,,b,,\n\
+             Bob,,,,40,cat\n"
+        );
+        assert_eq!(
+            to_csv(&[json!(1), json!("x")], ArrayMode::Join),
+            "value\n1\nx\n"
+        );
+    }
+
+    #[test]
+    fn test_file2csv() {
+        use clap::Parser;
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("rows.yaml");
+        let output = dir.path().join("rows.csv");
+        fs::write(&input, "- a: 1\n  b: [x, y]\n- a: 2\n").unwrap();
+        let (input, output) = (input.to_str().unwrap(), output.to_str().unwrap());
+        let opts = ToCsvOpts::try_parse_from([
+            "to-csv",
+            "-i",
+            input,
+            "-o",
+            output,
+            "-d",
+            ";",
+            "--separator",
+            ",",
+        ])
+        .unwrap();
+        assert_eq!(file2csv(opts).unwrap(), 2);
+        assert_eq!(fs::read_to_string(output).unwrap(), "a;b\n1;x,y\n2;\n");
+
+        let opts = ToCsvOpts::try_parse_from(["to-csv", "-i", "Cargo.lock"]).unwrap();
+        assert!(file2csv(opts).is_err());
+        // stdin has no extension to tell the format by
+        let opts = ToCsvOpts::try_parse_from(["to-csv", "-i", "-"]).unwrap();
+        assert!(file2csv(opts).is_err());
+    }
+}
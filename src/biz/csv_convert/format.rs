@@ -0,0 +1,432 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use anyhow::{anyhow, bail, Result};
+use csv::ReaderBuilder;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use super::flatten::flatten;
+use super::read_rows;
+use super::reader::sniff_delimiter;
+use crate::utils::html_escape;
+use crate::{ArrayMode, FileFormat};
+
+/// Serialize whole documents in `format`: one stays itself, several become
+/// a JSON array, YAML documents or `[[rows]]` in TOML. The row formats take
+/// the items of array documents as rows. `pretty` only changes JSON.
+pub fn format_documents(documents: &[Value], format: FileFormat, pretty: bool) -> Result<String> {
+    let output = match (format, documents) {
+        (FileFormat::Json, [document]) if pretty => serde_json::to_string_pretty(document)?,
+        (FileFormat::Json, [document]) => serde_json::to_string(document)?,
+        (FileFormat::Json, documents) if pretty => serde_json::to_string_pretty(documents)?,
+        (FileFormat::Json, documents) => serde_json::to_string(documents)?,
+        (FileFormat::Yaml, documents) => documents
+            .iter()
+            .map(serde_yaml::to_string)
+            .collect::<Result<Vec<_>, _>>()?
+            .join("---\n"),
+        (FileFormat::Toml, [document @ Value::Object(_)]) => {
+            toml::to_string(&without_nulls(std::slice::from_ref(document))[0])?
+        }
+        (format, documents) => format_rows(&rows_of(documents.to_vec()), format)?,
+    };
+    Ok(output)
+}
+
+/// Serialize `rows`, JSON objects, in `format`. The table formats take their
+/// columns from the keys of all rows, in the order they first appear; CSV
+/// and TSV flatten nested values as `to-csv` does.
+pub fn format_rows(rows: &[Value], format: FileFormat) -> Result<String> {
+    let mut columns: Vec<String> = Vec::new();
+    let mut add = |map: &Map<String, Value>| {
+        for key in map.keys() {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+    };
+    for row in rows {
+        match format {
+            FileFormat::Csv | FileFormat::Tsv => {
+                for flat in flatten(row, ArrayMode::Join, "|") {
+                    add(&flat.into_iter().map(|(k, _)| (k, Value::Null)).collect());
+                }
+            }
+            _ => {
+                if let Value::Object(map) = row {
+                    add(map);
+                }
+            }
+        }
+    }
+    let numeric: Vec<bool> = columns
+        .iter()
+        .map(|column| {
+            let mut values = rows.iter().filter_map(|row| row.get(column));
+            values.clone().any(Value::is_number) && values.all(|v| v.is_number() || v.is_null())
+        })
+        .collect();
+
+    let mut writer = RowWriter::new(Vec::new(), format, columns, &numeric)?;
+    for row in rows {
+        writer.write(row)?;
+    }
+    Ok(String::from_utf8(writer.finish()?)?)
+}
+
+/// Writes rows one at a time, so they need not all be held in memory; the
+/// output is the same as serializing them together.
+pub(super) struct RowWriter<W: Write> {
+    out: Sink<W>,
+    format: FileFormat,
+    columns: Vec<String>,
+    rows: usize,
+}
+
+impl<W: Write> RowWriter<W> {
+    /// `columns` are those of the table formats, where `numeric` ones line
+    /// up on the right in Markdown.
+    pub fn new(out: W, format: FileFormat, columns: Vec<String>, numeric: &[bool]) -> Result<Self> {
+        let out = match format {
+            FileFormat::Csv | FileFormat::Tsv => {
+                let delimiter = match format {
+                    FileFormat::Tsv => b'\t',
+                    _ => b',',
+                };
+                let writer = csv::WriterBuilder::new()
+                    .delimiter(delimiter)
+                    .from_writer(out);
+                Sink::Records(Box::new(writer))
+            }
+            _ => Sink::Text(out),
+        };
+        let mut writer = Self {
+            out,
+            format,
+            columns,
+            rows: 0,
+        };
+        match format {
+            FileFormat::Csv | FileFormat::Tsv => {
+                let columns = writer.columns.clone();
+                writer.write_record(&columns)?;
+            }
+            FileFormat::Markdown => {
+                let header: Vec<_> = writer.columns.iter().map(|c| markdown_escape(c)).collect();
+                let align = (0..header.len())
+                    .map(|i| match numeric.get(i) {
+                        Some(true) => "---:".to_string(),
+                        _ => "---".to_string(),
+                    })
+                    .collect();
+                writer.markdown_line(header)?;
+                writer.markdown_line(align)?;
+            }
+            FileFormat::Html => {
+                let mut head = String::from("<table>\n  <thead>\n    <tr>");
+                for column in &writer.columns {
+                    let _ = write!(head, "<th>{}</th>", html_escape(column));
+                }
+                head.push_str("</tr>\n  </thead>\n  <tbody>\n");
+                writer.out.write_all(head.as_bytes())?;
+            }
+            _ => {}
+        }
+        Ok(writer)
+    }
+
+    pub fn write(&mut self, row: &Value) -> Result<()> {
+        match self.format {
+            FileFormat::Json => {
+                let separator = if self.rows == 0 { "[\n" } else { ",\n" };
+                self.out.write_all(separator.as_bytes())?;
+                let text = serde_json::to_string_pretty(row)?;
+                for (i, line) in text.lines().enumerate() {
+                    let newline = if i == 0 { "" } else { "\n" };
+                    write!(self.out, "{}  {}", newline, line)?;
+                }
+            }
+            FileFormat::Yaml => {
+                let item = serde_yaml::to_string(std::slice::from_ref(row))?;
+                self.out.write_all(item.as_bytes())?;
+            }
+            FileFormat::Toml => {
+                if self.rows > 0 {
+                    self.out.write_all(b"\n")?;
+                }
+                // TOML has no null, absent keys stand in for them
+                let table =
+                    toml::to_string(&json!({ "rows": without_nulls(std::slice::from_ref(row)) }))?;
+                self.out.write_all(table.as_bytes())?;
+            }
+            FileFormat::Ndjson => {
+                serde_json::to_writer(&mut self.out, row)?;
+                self.out.write_all(b"\n")?;
+            }
+            FileFormat::Csv | FileFormat::Tsv => {
+                for flat in flatten(row, ArrayMode::Join, "|") {
+                    let record: Vec<_> = self
+                        .columns
+                        .iter()
+                        .map(|column| {
+                            flat.iter()
+                                .find(|(key, _)| key == column)
+                                .map_or(String::new(), |(_, cell)| cell.clone())
+                        })
+                        .collect();
+                    self.write_record(&record)?;
+                }
+            }
+            FileFormat::Markdown => {
+                let cells = self.cells(row).iter().map(|c| markdown_escape(c)).collect();
+                self.markdown_line(cells)?;
+            }
+            FileFormat::Html => {
+                let mut line = String::from("    <tr>");
+                for cell in self.cells(row) {
+                    let _ = write!(line, "<td>{}</td>", html_escape(&cell));
+                }
+                line.push_str("</tr>\n");
+                self.out.write_all(line.as_bytes())?;
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<W> {
+        let end = match (self.format, self.rows) {
+            (FileFormat::Json, 0) => "[]",
+            (FileFormat::Json, _) => "\n]",
+            (FileFormat::Yaml, 0) => "[]\n",
+            (FileFormat::Toml, 0) => "rows = []\n",
+            (FileFormat::Html, _) => "  </tbody>\n</table>\n",
+            _ => "",
+        };
+        match self.out {
+            Sink::Text(mut out) => {
+                out.write_all(end.as_bytes())?;
+                out.flush()?;
+                Ok(out)
+            }
+            Sink::Records(writer) => Ok(writer.into_inner().map_err(|e| e.into_error())?),
+        }
+    }
+
+    fn cells(&self, row: &Value) -> Vec<String> {
+        self.columns
+            .iter()
+            .map(|column| row.get(column).map(cell).unwrap_or_default())
+            .collect()
+    }
+
+    fn markdown_line(&mut self, cells: Vec<String>) -> Result<()> {
+        writeln!(self.out, "| {} |", cells.join(" | "))?;
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &[String]) -> Result<()> {
+        if let Sink::Records(writer) = &mut self.out {
+            writer.write_record(record)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a [`RowWriter`] writes: CSV and TSV records go through one
+/// `csv::Writer` for the whole output, the other formats straight to `W`.
+enum Sink<W: Write> {
+    Text(W),
+    Records(Box<csv::Writer<W>>),
+}
+
+impl<W: Write> Write for Sink<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Text(out) => out.write(buf),
+            Sink::Records(_) => unreachable!("CSV and TSV are written record by record"),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Text(out) => out.flush(),
+            Sink::Records(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Read the documents of `content`: every value of a JSON stream, YAML
+/// document or NDJSON line; a TOML file; the rows of a CSV or TSV file, with
+/// column types inferred, as one array.
+pub fn parse_documents(content: &str, format: FileFormat) -> Result<Vec<Value>> {
+    let mut documents = Vec::new();
+    match format {
+        FileFormat::Json => {
+            for document in serde_json::Deserializer::from_str(content).into_iter() {
+                documents.push(document?);
+            }
+        }
+        FileFormat::Ndjson => {
+            for (n, line) in content.lines().enumerate() {
+                if !line.trim().is_empty() {
+                    let document =
+                        serde_json::from_str(line).map_err(|e| anyhow!("Line {}: {}", n + 1, e))?;
+                    documents.push(document);
+                }
+            }
+        }
+        FileFormat::Yaml => {
+            for document in serde_yaml::Deserializer::from_str(content) {
+                documents.push(Value::deserialize(document)?);
+            }
+        }
+        FileFormat::Toml => documents.push(toml::from_str(content)?),
+        FileFormat::Csv | FileFormat::Tsv => {
+            let delimiter = match format {
+                FileFormat::Tsv => b'\t',
+                _ => sniff_delimiter(content.as_bytes(), b'"', None),
+            };
+            let mut rdr = ReaderBuilder::new()
+                .delimiter(delimiter)
+                .from_reader(content.as_bytes());
+            documents.push(Value::Array(read_rows(&mut rdr, true, &[], true, false)?));
+        }
+        FileFormat::Markdown | FileFormat::Html => {
+            bail!("Reading {} is not supported", format)
+        }
+    }
+    Ok(documents)
+}
+
+/// Read rows back from any format `parse_documents` reads. Top level arrays
+/// hold the rows, anything else is a single row; in TOML, a lone array of
+/// tables such as `[[rows]]` holds them.
+pub fn parse_rows(content: &str, format: FileFormat) -> Result<Vec<Value>> {
+    let mut documents = parse_documents(content, format)?;
+    if let (FileFormat::Toml, [Value::Object(table)]) = (format, documents.as_mut_slice()) {
+        if table.len() == 1 && table.values().all(Value::is_array) {
+            documents = table.values_mut().map(Value::take).collect();
+        }
+    }
+    Ok(rows_of(documents))
+}
+
+fn rows_of(documents: Vec<Value>) -> Vec<Value> {
+    let mut rows = Vec::new();
+    for document in documents {
+        match document {
+            Value::Array(items) => rows.extend(items),
+            other => rows.push(other),
+        }
+    }
+    rows
+}
+
+pub(super) fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn markdown_escape(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', "<br>")
+}
+
+fn without_nulls(rows: &[Value]) -> Vec<Value> {
+    fn strip(value: &Value) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .filter(|(_, v)| !v.is_null())
+                    .map(|(k, v)| (k.clone(), strip(v)))
+                    .collect(),
+            ),
+            Value::Array(items) => Value::Array(items.iter().map(strip).collect()),
+            other => other.clone(),
+        }
+    }
+    rows.iter().map(strip).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows() -> Vec<Value> {
+        vec![
+            json!({"Name": "Ada", "Age": 36, "Note": "a|b <c>"}),
+            json!({"Name": "Bob", "Age": null, "Team": "x\ty"}),
+        ]
+    }
+
+    #[test]
+    fn test_format_rows() {
+        let format = |format| format_rows(&rows(), format).unwrap();
+        assert_eq!(
+            format(FileFormat::Ndjson),
+            "{\"Name\":\"Ada\",\"Age\":36,\"Note\":\"a|b <c>\"}\n\
+             {\"Name\":\"Bob\",\"Age\":null,\"Team\":\"x\\ty\"}\n"
+        );
+        assert_eq!(
+            format(FileFormat::Toml),
+            "[[rows]]\nName = \"Ada\"\nAge = 36\nNote = \"a|b <c>\"\n\n\
+             [[rows]]\nName = \"Bob\"\nTeam = \"x\\ty\"\n"
+        );
+        assert_eq!(
+            format(FileFormat::Tsv),
+            "Name\tAge\tNote\tTeam\nAda\t36\ta|b <c>\t\nBob\t\t\t\"x\ty\"\n"
+        );
+        assert_eq!(
+            format(FileFormat::Markdown),
+            "| Name | Age | Note | Team |\n\
+             | --- | ---: | --- | --- |\n\
+             | Ada | 36 | a\\|b <c> |  |\n\
+             | Bob |  |  | x\ty |\n"
+        );
+        assert_eq!(
+            format(FileFormat::Html),
+            "<table>\n  <thead>\n    \
+             <tr><th>Name</th><th>Age</th><th>Note</th><th>Team</th></tr>\n  \
+             </thead>\n  <tbody>\n    \
+             <tr><td>Ada</td><td>36</td><td>a|b &lt;c&gt;</td><td></td></tr>\n    \
+             <tr><td>Bob</td><td></td><td></td><td>x\ty</td></tr>\n  \
+             </tbody>\n</table>\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_rows() {
+        for format in [
+            FileFormat::Json,
+            FileFormat::Yaml,
+            FileFormat::Toml,
+            FileFormat::Ndjson,
+        ] {
+            let text = format_rows(&rows(), format).unwrap();
+            let parsed = parse_rows(&text, format).unwrap();
+            if let FileFormat::Toml = format {
+                assert_eq!(parsed, without_nulls(&rows()), "{}", format);
+            } else {
+                assert_eq!(parsed, rows(), "{}", format);
+            }
+        }
+        assert_eq!(
+            parse_rows("a: 1\n---\n- a: 2\n- a: 3\n", FileFormat::Yaml).unwrap(),
+            [json!({"a": 1}), json!({"a": 2}), json!({"a": 3})]
+        );
+        assert_eq!(
+            parse_rows("a = 1\n[b]\nc = 2\n", FileFormat::Toml).unwrap(),
+            [json!({"a": 1, "b": {"c": 2}})]
+        );
+        assert!(parse_rows("{}\n{", FileFormat::Ndjson).is_err());
+        assert_eq!(
+            parse_rows("a\tb\n1\tx\n", FileFormat::Tsv).unwrap(),
+            [json!({"a": 1, "b": "x"})]
+        );
+        assert!(parse_rows("<table>", FileFormat::Html).is_err());
+    }
+}
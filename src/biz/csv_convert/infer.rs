@@ -0,0 +1,253 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use csv::StringRecord;
+use serde_json::{Number, Value};
+
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y/%m/%d", "%d.%m.%Y"];
+const DATETIME_FORMATS: &[&str] = &["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"];
+
+/// The type of a CSV column, inferred or given with `--type Column=type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColumnType {
+    // ordered from the narrowest, see `widen`
+    Bool,
+    Int,
+    Float,
+    Date,
+    String,
+}
+
+impl FromStr for ColumnType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bool" | "boolean" => Ok(ColumnType::Bool),
+            "int" | "integer" => Ok(ColumnType::Int),
+            "float" | "number" => Ok(ColumnType::Float),
+            "date" => Ok(ColumnType::Date),
+            "string" | "str" => Ok(ColumnType::String),
+            _ => Err("Invalid column type, expected string, int, float, bool or date"),
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColumnType::Bool => "bool",
+            ColumnType::Int => "int",
+            ColumnType::Float => "float",
+            ColumnType::Date => "date",
+            ColumnType::String => "string",
+        };
+        f.write_str(name)
+    }
+}
+
+impl ColumnType {
+    /// The narrowest type `value` fits, `None` for an empty cell.
+    fn of(value: &str, dates: bool) -> Option<Self> {
+        if value.is_empty() {
+            None
+        } else if parse_bool(value).is_some() {
+            Some(ColumnType::Bool)
+        } else if parse_int(value).is_some() {
+            Some(ColumnType::Int)
+        } else if parse_float(value).is_some() {
+            Some(ColumnType::Float)
+        } else if dates && parse_date(value).is_some() {
+            Some(ColumnType::Date)
+        } else {
+            Some(ColumnType::String)
+        }
+    }
+
+    /// A type both `self` and `other` values fit.
+    fn widen(self, other: Self) -> Self {
+        match (self.min(other), self.max(other)) {
+            (a, b) if a == b => a,
+            (ColumnType::Int, ColumnType::Float) => ColumnType::Float,
+            _ => ColumnType::String,
+        }
+    }
+
+    /// Convert a cell; empty cells are null.
+    pub fn convert(self, value: &str) -> Result<Value, String> {
+        if value.is_empty() {
+            return Ok(Value::Null);
+        }
+        let converted = match self {
+            ColumnType::String => Some(Value::String(value.to_string())),
+            ColumnType::Bool => parse_bool(value).map(Value::Bool),
+            ColumnType::Int => parse_int(value).map(Value::from),
+            ColumnType::Float => parse_float(value)
+                .and_then(Number::from_f64)
+                .map(Value::Number),
+            ColumnType::Date => parse_date(value).map(Value::String),
+        };
+        converted.ok_or_else(|| format!("cannot read {:?} as {}", value, self))
+    }
+}
+
+/// Column types for `records`: the `overrides` by column name, and for the
+/// others the narrowest type every value fits when `infer` is set, or `None`
+/// to keep the raw strings.
+pub fn column_types(
+    headers: &StringRecord,
+    records: &[StringRecord],
+    overrides: &[(String, ColumnType)],
+    infer: bool,
+    dates: bool,
+) -> Result<Vec<Option<ColumnType>>> {
+    let mut types: Vec<Option<ColumnType>> = vec![None; headers.len()];
+    if infer {
+        for (i, ty) in types.iter_mut().enumerate() {
+            *ty = records
+                .iter()
+                .filter_map(|record| ColumnType::of(record.get(i).unwrap_or_default(), dates))
+                .reduce(ColumnType::widen)
+                // nothing but empty cells, which are null either way
+                .or(Some(ColumnType::String));
+        }
+    }
+    for (name, ty) in overrides {
+        let i = headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| anyhow!("No column {} to set the type of", name))?;
+        types[i] = Some(*ty);
+    }
+    Ok(types)
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_int(value: &str) -> Option<i64> {
+    if leading_zero(value) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+fn parse_float(value: &str) -> Option<f64> {
+    // rust also accepts inf and NaN, which JSON has no numbers for
+    if leading_zero(value) || !value.bytes().any(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok().filter(|f: &f64| f.is_finite())
+}
+
+// leading zeros mark identifiers such as zip codes, keep them as text
+fn leading_zero(value: &str) -> bool {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    let whole = digits.split(['.', 'e', 'E']).next().unwrap_or_default();
+    whole.len() > 1 && whole.starts_with('0')
+}
+
+/// The date in ISO 8601 form.
+fn parse_date(value: &str) -> Option<String> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(datetime.to_rfc3339());
+    }
+    if let Some(datetime) = DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    {
+        return Some(datetime.format("%Y-%m-%dT%H:%M:%S").to_string());
+    }
+    DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+        .map(|date| date.format("%Y-%m-%d").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn types(rows: &[&[&str]], overrides: &[(&str, ColumnType)], dates: bool) -> Vec<String> {
+        let headers = StringRecord::from(vec!["a", "b", "c"]);
+        let records: Vec<_> = rows
+            .iter()
+            .map(|row| StringRecord::from(row.to_vec()))
+            .collect();
+        let overrides: Vec<_> = overrides
+            .iter()
+            .map(|(name, ty)| (name.to_string(), *ty))
+            .collect();
+        column_types(&headers, &records, &overrides, true, dates)
+            .unwrap()
+            .iter()
+            .map(|ty| ty.unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_column_types() {
+        let rows: &[&[&str]] = &[
+            &["1", "true", "2024-01-31"],
+            &["", "False", "x"],
+            &["2", "", ""],
+        ];
+        assert_eq!(types(rows, &[], false), ["int", "bool", "string"]);
+        assert_eq!(types(&rows[..1], &[], true), ["int", "bool", "date"]);
+        assert_eq!(
+            types(rows, &[("a", ColumnType::String)], false),
+            ["string", "bool", "string"]
+        );
+
+        let rows: &[&[&str]] = &[&["1", "1", "007"], &["1.5", "yes", "7"]];
+        assert_eq!(types(rows, &[], false), ["float", "string", "string"]);
+        let rows: &[&[&str]] = &[&["", "", ""]];
+        assert_eq!(types(rows, &[], false), ["string", "string", "string"]);
+
+        let headers = StringRecord::from(vec!["a"]);
+        let overrides = [("b".to_string(), ColumnType::Int)];
+        assert!(column_types(&headers, &[], &overrides, true, false).is_err());
+        let raw = column_types(&headers, &[], &[], false, false).unwrap();
+        assert_eq!(raw, [None]);
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(ColumnType::Int.convert("-42"), Ok(json!(-42)));
+        assert_eq!(ColumnType::Float.convert("42"), Ok(json!(42.0)));
+        assert_eq!(ColumnType::Float.convert("1e3"), Ok(json!(1000.0)));
+        assert_eq!(ColumnType::Bool.convert("TRUE"), Ok(json!(true)));
+        assert_eq!(ColumnType::String.convert("1"), Ok(json!("1")));
+        assert_eq!(ColumnType::Int.convert(""), Ok(Value::Null));
+        assert_eq!(
+            ColumnType::Date.convert("31.01.2024"),
+            Ok(json!("2024-01-31"))
+        );
+        assert_eq!(
+            ColumnType::Date.convert("2024-01-31 08:00:00"),
+            Ok(json!("2024-01-31T08:00:00"))
+        );
+        assert_eq!(
+            ColumnType::Date.convert("2024-01-31T08:00:00Z"),
+            Ok(json!("2024-01-31T08:00:00+00:00"))
+        );
+        assert_eq!(
+            ColumnType::Int.convert("1.5"),
+            Err("cannot read \"1.5\" as int".to_string())
+        );
+        assert!(ColumnType::Float.convert("NaN").is_err());
+        assert_eq!(ColumnType::Float.convert("0.5"), Ok(json!(0.5)));
+        assert!(ColumnType::Float.convert("01.5").is_err());
+        assert_eq!("Integer".parse(), Ok(ColumnType::Int));
+        assert!("decimal".parse::<ColumnType>().is_err());
+    }
+}
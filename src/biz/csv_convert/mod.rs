@@ -0,0 +1,376 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, Error, Result};
+use colored::Colorize;
+use csv::{Reader, StringRecord};
+use serde_json::{Map, Value};
+
+use crate::{CsvOpts, CsvReadOpts, FileFormat};
+
+pub use filter::RowFilter;
+pub use flatten::file2csv;
+use format::RowWriter;
+pub use format::{format_documents, format_rows, parse_documents, parse_rows};
+pub use infer::{column_types, ColumnType};
+use progress::Progress;
+use query::Query;
+use reader::{complete_headers, csv_reader};
+pub use stats::{csv_schema, csv_stats};
+
+mod filter;
+mod flatten;
+mod format;
+mod infer;
+mod progress;
+mod query;
+mod reader;
+mod stats;
+
+/// Convert CSV row by row, so the input may be larger than memory; column
+/// types are inferred from the first `--infer-rows` records. Only `--sort-by`
+/// holds the rows in memory. Returns the number of rows written.
+pub fn csv2file(opts: CsvOpts) -> Result<usize, Error> {
+    let format = opts
+        .format
+        .or_else(|| {
+            let output = Path::new(opts.output.as_deref()?);
+            output.extension()?.to_str()?.parse().ok()
+        })
+        .unwrap_or(FileFormat::Json);
+    let output = match opts.output.clone() {
+        Some(output) => output,
+        None => {
+            let output = format!("output.{}", format);
+            output
+        }
+    };
+    let to_stdout = output == "-";
+    if !to_stdout {
+        println!("{} {}", "Output file: ".blue(), output.blue());
+    }
+    let mut rdr = csv_reader(&opts.input, &opts.read)?;
+    let headers = match opts.read.header {
+        true => Some(rdr.headers()?.clone()),
+        false => None,
+    };
+    let mut record = StringRecord::new();
+    let mut sample = Vec::new();
+    while sample.len() < opts.infer_rows && rdr.read_record(&mut record)? {
+        sample.push(record.clone());
+    }
+    let columns = Columns::new(headers.as_ref(), &sample, &opts.read)?;
+
+    let out: Box<dyn Write> = match to_stdout {
+        true => Box::new(io::stdout().lock()),
+        false => Box::new(BufWriter::new(File::create(&output)?)),
+    };
+    let numeric: Vec<bool> = columns
+        .types
+        .iter()
+        .map(|ty| matches!(ty, Some(ColumnType::Int | ColumnType::Float)))
+        .collect();
+    let mut query = Query::new(&opts, &columns.headers)?;
+    let (names, numeric) = query.output(&columns.headers, &numeric);
+    let mut writer = RowWriter::new(out, format, names, &numeric)?;
+    let mut rows = 0;
+    // whether more rows may still be written
+    let mut write = |record: &StringRecord| -> Result<bool> {
+        if let Some(row) = query.push(columns.row(record)?) {
+            writer.write(&row)?;
+            rows += 1;
+        }
+        Ok(!query.done())
+    };
+    let mut more = true;
+    for record in &sample {
+        if more {
+            more = write(record)?;
+        }
+    }
+    let mut read = sample.len();
+    let mut progress = Progress::new();
+    while more && rdr.read_record(&mut record)? {
+        more = write(&record)?;
+        read += 1;
+        progress.update(read, rdr.position().byte());
+    }
+    for row in query.finish() {
+        writer.write(&row)?;
+        rows += 1;
+    }
+    writer.finish()?;
+    progress.finish();
+
+    if opts.print && !to_stdout {
+        print!("{}", fs::read_to_string(&output)?);
+    }
+    Ok(rows)
+}
+
+/// Every record as a JSON object keyed by the column names, typed per
+/// column as `column_types` decides.
+fn read_rows<R: Read>(
+    rdr: &mut Reader<R>,
+    header: bool,
+    overrides: &[(String, ColumnType)],
+    infer: bool,
+    dates: bool,
+) -> Result<Vec<Value>> {
+    let (columns, records) = read_table(rdr, header, overrides, infer, dates)?;
+    records.iter().map(|record| columns.row(record)).collect()
+}
+
+/// Every record, with the columns typed from all of them.
+fn read_table<R: Read>(
+    rdr: &mut Reader<R>,
+    header: bool,
+    overrides: &[(String, ColumnType)],
+    infer: bool,
+    dates: bool,
+) -> Result<(Columns, Vec<StringRecord>)> {
+    let headers = match header {
+        true => Some(rdr.headers()?.clone()),
+        false => None,
+    };
+    let records = rdr.records().collect::<Result<Vec<_>, _>>()?;
+    // --flexible records may run past the header row
+    let width = records.iter().map(|record| record.len()).max();
+    let headers = complete_headers(headers.as_ref(), width.unwrap_or_default());
+    let types = column_types(&headers, &records, overrides, infer, dates)?;
+    let columns = Columns {
+        strict: vec![true; headers.len()],
+        headers,
+        types,
+    };
+    Ok((columns, records))
+}
+
+/// How the fields of a record become a row.
+struct Columns {
+    headers: StringRecord,
+    types: Vec<Option<ColumnType>>,
+    // values that do not fit the type are an error, else they stay strings
+    strict: Vec<bool>,
+}
+
+impl Columns {
+    /// Columns typed from the `sample` of the first records.
+    fn new(
+        headers: Option<&StringRecord>,
+        sample: &[StringRecord],
+        opts: &CsvReadOpts,
+    ) -> Result<Self> {
+        // --flexible records may run past the header row
+        let width = sample.iter().map(|record| record.len()).max();
+        let headers = complete_headers(headers, width.unwrap_or_default());
+        let types = column_types(
+            &headers,
+            sample,
+            &opts.types,
+            !opts.no_infer,
+            opts.infer_dates,
+        )?;
+        // only the overrides hold past the sample, later values may not fit an
+        // inferred type
+        let strict = headers
+            .iter()
+            .map(|header| opts.types.iter().any(|(name, _)| name == header))
+            .collect();
+        Ok(Self {
+            headers,
+            types,
+            strict,
+        })
+    }
+
+    fn row(&self, record: &StringRecord) -> Result<Value> {
+        // header.iter()  使用headers的迭代器
+        // zip()  将两个迭代器合并成一个元组
+        // 短记录缺少的字段按空值处理
+        let mut player = Map::new();
+        for (i, (header, ty)) in self.headers.iter().zip(&self.types).enumerate() {
+            let field = record.get(i).unwrap_or_default();
+            let value = match ty {
+                Some(ty) => match ty.convert(field) {
+                    Ok(value) => value,
+                    Err(_) if !self.strict.get(i).copied().unwrap_or_default() => {
+                        Value::String(field.to_string())
+                    }
+                    Err(e) => {
+                        let line = record.position().map_or(0, |p| p.line());
+                        return Err(anyhow!("Line {}, column {}: {}", line, header, e));
+                    }
+                },
+                None => Value::String(field.to_string()),
+            };
+            player.insert(header.to_string(), value);
+        }
+        // records longer than the sampled ones
+        for i in self.headers.len()..record.len() {
+            player.insert(
+                format!("col{}", i + 1),
+                Value::String(record[i].to_string()),
+            );
+        }
+        Ok(Value::Object(player))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use serde_json::json;
+
+    use super::*;
+
+    fn convert(csv: &str, args: &[&str]) -> anyhow::Result<Value> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("input.csv");
+        let output = dir.path().join("output.json");
+        fs::write(&input, csv)?;
+        let (input, output) = (input.to_str().unwrap(), output.to_str().unwrap());
+        let base = ["csv", "-i", input, "-o", output];
+        let opts = CsvOpts::try_parse_from(base.iter().chain(args))?;
+        csv2file(opts)?;
+        Ok(serde_json::from_str(&fs::read_to_string(output)?)?)
+    }
+
+    #[test]
+    fn test_csv2file_types() {
+        let csv = "Name,Age,Score,Active,Zip\nAda,36,9.5,true,01234\nBob,,7,false,\n";
+        assert_eq!(
+            convert(csv, &[]).unwrap(),
+            json!([
+                {"Name": "Ada", "Age": 36, "Score": 9.5, "Active": true, "Zip": "01234"},
+                {"Name": "Bob", "Age": null, "Score": 7.0, "Active": false, "Zip": null}
+            ])
+        );
+        assert_eq!(
+            convert(csv, &["--no-infer", "--type", "Age=float"]).unwrap()[0],
+            json!({"Name": "Ada", "Age": 36.0, "Score": "9.5", "Active": "true", "Zip": "01234"})
+        );
+        let err = convert(csv, &["--type", "Name=int"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Line 2, column Name: cannot read \"Ada\" as int"
+        );
+        assert!(convert(csv, &["--type", "Age=decimal"]).is_err());
+    }
+
+    #[test]
+    fn test_csv2file_reader_options() {
+        let csv = "# exported\nName;Note\nAda;'a;b'\n";
+        // the comment line and the unquoted ; make records of different lengths
+        assert!(convert(csv, &[]).is_err());
+        assert_eq!(
+            convert(csv, &["--comment", "#", "--quote", "'"]).unwrap(),
+            json!([{"Name": "Ada", "Note": "a;b"}])
+        );
+        assert_eq!(
+            convert("1\t2\n3\t4\t5\n", &["--header", "false", "--flexible"]).unwrap(),
+            json!([
+                {"col1": 1, "col2": 2, "col3": null},
+                {"col1": 3, "col2": 4, "col3": 5}
+            ])
+        );
+        assert_eq!(
+            convert("a|b\n\"x\\\"y\"|1\n", &["-d", "|", "--escape", "\\"]).unwrap(),
+            json!([{"a": "x\"y", "b": 1}])
+        );
+        assert!(convert("a,b\n1,2,3\n", &[]).is_err());
+        assert!(convert("a,b\n", &["-d", "ab"]).is_err());
+    }
+
+    #[test]
+    fn test_csv2file_streaming() {
+        let csv = "id,score\n1,2\n2,x\n3,\n";
+        // the type comes from the first row, later misfits stay strings
+        assert_eq!(
+            convert(csv, &["--infer-rows", "1"]).unwrap(),
+            json!([
+                {"id": 1, "score": 2},
+                {"id": 2, "score": "x"},
+                {"id": 3, "score": null}
+            ])
+        );
+        assert!(convert(csv, &["--infer-rows", "1", "--type", "score=int"]).is_err());
+        assert_eq!(convert("id\n", &[]).unwrap(), json!([]));
+        assert_eq!(
+            convert("a\n1\n2,3\n", &["--flexible", "--infer-rows", "1"]).unwrap(),
+            json!([{"a": 1}, {"a": 2, "col2": "3"}])
+        );
+    }
+
+    #[test]
+    fn test_csv2file_query() {
+        let juventus = fs::read_to_string("assets/juventus.csv").unwrap();
+        let query = |args: &[&str]| convert(&juventus, args).unwrap();
+        assert_eq!(
+            query(&[
+                "--where",
+                "Position == 'Goalkeeper' and `Kit Number` > 1",
+                "--select",
+                "Name,Kit Number",
+                "--rename",
+                "Kit Number=Number",
+                "--sort-by",
+                "Kit Number",
+                "--desc",
+                "--limit",
+                "2",
+            ]),
+            json!([
+                {"Name": "Gianluigi Buffon", "Number": 77},
+                {"Name": "Mattia Perin", "Number": 37}
+            ])
+        );
+        assert_eq!(
+            query(&["--select", "Position", "--distinct", "--offset", "8"]),
+            json!([{"Position": "Second Striker"}, {"Position": "Centre-Forward"}])
+        );
+        let rows = query(&["--exclude", "DOB,Nationality", "--limit", "1"]);
+        assert_eq!(
+            rows,
+            json!([{"Name": "Wojciech Szczesny", "Position": "Goalkeeper", "Kit Number": 1}])
+        );
+        assert_eq!(
+            query(&["--where", "Name contains 'zz'", "--limit", "0"]),
+            json!([])
+        );
+
+        let err = convert(&juventus, &["--where", "Age > 30"]).unwrap_err();
+        assert_eq!(err.to_string(), "No column Age");
+        assert!(convert(&juventus, &["--where", "Age >"]).is_err());
+        assert!(convert(&juventus, &["--desc"]).is_err());
+    }
+
+    #[test]
+    fn test_csv2file_format_from_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.csv");
+        fs::write(&input, "Name,Age\nAda,36\n").unwrap();
+        let run = |output: &str, args: &[&str]| {
+            let output = dir.path().join(output);
+            let base = [
+                "csv",
+                "-i",
+                input.to_str().unwrap(),
+                "-o",
+                output.to_str().unwrap(),
+            ];
+            let opts = CsvOpts::try_parse_from(base.iter().chain(args)).unwrap();
+            csv2file(opts).unwrap();
+            fs::read_to_string(output).unwrap()
+        };
+        assert_eq!(run("rows.jsonl", &[]), "{\"Name\":\"Ada\",\"Age\":36}\n");
+        assert_eq!(run("rows.tsv", &[]), "Name\tAge\nAda\t36\n");
+        assert_eq!(run("rows.yml", &[]), "- Name: Ada\n  Age: 36\n");
+        assert_eq!(
+            run("rows.txt", &["--format", "md"]),
+            "| Name | Age |\n| --- | ---: |\n| Ada | 36 |\n"
+        );
+        assert!(run("rows.out", &[]).starts_with("[\n  {\n    \"Name\""));
+    }
+}
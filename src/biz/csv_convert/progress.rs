@@ -0,0 +1,44 @@
+use std::io::{self, IsTerminal};
+use std::time::{Duration, Instant};
+
+const INTERVAL: Duration = Duration::from_millis(250);
+
+/// A line on stderr counting rows and bytes read, shown only on a terminal.
+pub(super) struct Progress {
+    enabled: bool,
+    shown: bool,
+    last: Instant,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self {
+            enabled: io::stderr().is_terminal(),
+            shown: false,
+            last: Instant::now(),
+        }
+    }
+
+    // is_multiple_of needs Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn update(&mut self, rows: usize, bytes: u64) {
+        // checking the clock on every row would cost more than the rows
+        if !self.enabled || rows % 1024 != 0 || self.last.elapsed() < INTERVAL {
+            return;
+        }
+        self.last = Instant::now();
+        self.shown = true;
+        eprint!(
+            "\r{} rows, {:.1} MiB read",
+            rows,
+            bytes as f64 / (1 << 20) as f64
+        );
+    }
+
+    pub fn finish(&self) {
+        if self.shown {
+            // clear the line
+            eprint!("\r\x1b[K");
+        }
+    }
+}
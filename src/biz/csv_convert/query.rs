@@ -0,0 +1,180 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use serde_json::{Map, Value};
+
+use super::filter::{compare, RowFilter};
+use crate::CsvOpts;
+
+/// `--where`, `--select`, `--exclude`, `--rename`, `--distinct`, `--sort-by`,
+/// `--offset` and `--limit` applied to rows as they are read. Conditions and
+/// sort keys name the columns of the input, before any renaming.
+pub(super) struct Query {
+    filter: Option<RowFilter>,
+    // (input, output) names, or every column as read
+    columns: Option<Vec<(String, String)>>,
+    seen: Option<HashSet<String>>,
+    sort_by: Vec<String>,
+    desc: bool,
+    offset: usize,
+    limit: Option<usize>,
+    // sorting needs every row before the first can be written
+    sorted: Vec<(Vec<Value>, Value)>,
+    skipped: usize,
+    taken: usize,
+}
+
+impl Query {
+    pub fn new(opts: &CsvOpts, headers: &StringRecord) -> Result<Self> {
+        let names = opts
+            .filter
+            .iter()
+            .flat_map(|filter| filter.columns())
+            .chain(opts.select.iter().map(String::as_str))
+            .chain(opts.exclude.iter().map(String::as_str))
+            .chain(opts.rename.iter().map(|(old, _)| old.as_str()))
+            .chain(opts.sort_by.iter().map(String::as_str));
+        for name in names {
+            if !headers.iter().any(|header| header == name) {
+                return Err(anyhow!("No column {}", name));
+            }
+        }
+
+        let projected =
+            !opts.select.is_empty() || !opts.exclude.is_empty() || !opts.rename.is_empty();
+        let columns = projected.then(|| {
+            let selected: Vec<&str> = match opts.select.is_empty() {
+                true => headers.iter().collect(),
+                false => opts.select.iter().map(String::as_str).collect(),
+            };
+            selected
+                .into_iter()
+                .filter(|name| !opts.exclude.iter().any(|excluded| excluded == name))
+                .map(|name| {
+                    let output = opts
+                        .rename
+                        .iter()
+                        .rfind(|(old, _)| old == name)
+                        .map_or(name, |(_, new)| new.as_str());
+                    (name.to_string(), output.to_string())
+                })
+                .collect()
+        });
+        Ok(Self {
+            filter: opts.filter.clone(),
+            columns,
+            seen: opts.distinct.then(HashSet::new),
+            sort_by: opts.sort_by.clone(),
+            desc: opts.desc,
+            offset: opts.offset,
+            limit: opts.limit,
+            sorted: Vec::new(),
+            skipped: 0,
+            taken: 0,
+        })
+    }
+
+    /// The names of the output columns and whether each is numeric.
+    pub fn output(&self, headers: &StringRecord, numeric: &[bool]) -> (Vec<String>, Vec<bool>) {
+        match &self.columns {
+            None => (headers.iter().map(String::from).collect(), numeric.to_vec()),
+            Some(columns) => columns
+                .iter()
+                .map(|(input, output)| {
+                    let i = headers.iter().position(|header| header == input);
+                    (output.clone(), i.is_some_and(|i| numeric[i]))
+                })
+                .unzip(),
+        }
+    }
+
+    /// The row to write now, if any; sorted rows wait for `finish`.
+    pub fn push(&mut self, row: Value) -> Option<Value> {
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !filter.matches(&row))
+        {
+            return None;
+        }
+        let key: Vec<Value> = self
+            .sort_by
+            .iter()
+            .map(|name| row.get(name).cloned().unwrap_or_default())
+            .collect();
+        let row = self.project(row);
+        if let Some(seen) = &mut self.seen {
+            if !seen.insert(row.to_string()) {
+                return None;
+            }
+        }
+        if !self.sort_by.is_empty() {
+            self.sorted.push((key, row));
+            return None;
+        }
+        if self.skipped < self.offset {
+            self.skipped += 1;
+            return None;
+        }
+        if self.done() {
+            return None;
+        }
+        self.taken += 1;
+        Some(row)
+    }
+
+    /// Whether no more rows will be written, so reading may stop.
+    pub fn done(&self) -> bool {
+        self.sort_by.is_empty() && self.limit.is_some_and(|limit| self.taken >= limit)
+    }
+
+    /// The sorted rows, once all are read.
+    pub fn finish(mut self) -> Vec<Value> {
+        let desc = self.desc;
+        // stable, so ties keep the input order
+        self.sorted.sort_by(|(a, _), (b, _)| {
+            a.iter()
+                .zip(b)
+                .map(|(a, b)| order(a, b, desc))
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+        self.sorted
+            .into_iter()
+            .map(|(_, row)| row)
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    fn project(&self, row: Value) -> Value {
+        match (&self.columns, row) {
+            (Some(columns), Value::Object(mut map)) => Value::Object(
+                columns
+                    .iter()
+                    .map(|(input, output)| (output.clone(), map.remove(input).unwrap_or_default()))
+                    .collect::<Map<_, _>>(),
+            ),
+            (_, row) => row,
+        }
+    }
+}
+
+/// Nulls last whichever the direction.
+fn order(a: &Value, b: &Value, desc: bool) -> Ordering {
+    match (a.is_null(), b.is_null()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        _ => {
+            let ordering = compare(a, b).unwrap_or(Ordering::Equal);
+            if desc {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+
+use anyhow::Result;
+use csv::{Reader, ReaderBuilder, StringRecord};
+
+use crate::CsvReadOpts;
+
+const DELIMITERS: &[u8] = b",\t;|";
+// enough lines to tell a delimiter from punctuation in the data
+const SAMPLE_LINES: usize = 20;
+const SAMPLE_SIZE: u64 = 64 << 10;
+
+/// A reader of the file or stdin configured from the options, sniffing the
+/// delimiter from the start of the input unless `--delimiter` is given.
+pub(super) fn csv_reader(input: &str, opts: &CsvReadOpts) -> Result<Reader<Box<dyn Read>>> {
+    let mut input: Box<dyn Read> = match input {
+        "-" => Box::new(io::stdin()),
+        path => Box::new(File::open(path)?),
+    };
+    let delimiter = match opts.delimiter {
+        Some(delimiter) => delimiter,
+        None => {
+            let mut sample = Vec::new();
+            (&mut input).take(SAMPLE_SIZE).read_to_end(&mut sample)?;
+            let delimiter = sniff_delimiter(&sample, opts.quote, opts.comment);
+            // stdin cannot be read twice, put the sample back in front
+            input = Box::new(Cursor::new(sample).chain(input));
+            delimiter
+        }
+    };
+    let reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(opts.header)
+        .quote(opts.quote)
+        .escape(opts.escape)
+        .comment(opts.comment)
+        .flexible(opts.flexible)
+        .from_reader(input);
+    Ok(reader)
+}
+
+/// Headers for records up to `width` fields: `col1..colN` without a header
+/// row, and named the same way past its end when records are longer.
+pub(super) fn complete_headers(headers: Option<&StringRecord>, width: usize) -> StringRecord {
+    let mut headers = headers.cloned().unwrap_or_default();
+    for i in headers.len()..width {
+        headers.push_field(&format!("col{}", i + 1));
+    }
+    headers
+}
+
+/// The candidate found the same number of times, outside quotes, on every
+/// sampled line, the most frequent such one; else the one on every line; or
+/// a comma.
+pub(super) fn sniff_delimiter(sample: &[u8], quote: u8, comment: Option<u8>) -> u8 {
+    let mut lines = Vec::new();
+    let mut counts = [0usize; DELIMITERS.len()];
+    let mut quoted = false;
+    let mut line_start = true;
+    let mut skip_line = false;
+    for &b in sample {
+        if line_start {
+            line_start = false;
+            skip_line = Some(b) == comment;
+        }
+        if b == quote {
+            quoted = !quoted;
+        } else if b == b'\n' && !quoted {
+            if !skip_line && counts.iter().any(|&c| c > 0) {
+                lines.push(counts);
+            }
+            counts = [0; DELIMITERS.len()];
+            line_start = true;
+            if lines.len() == SAMPLE_LINES {
+                break;
+            }
+        } else if !quoted {
+            if let Some(i) = DELIMITERS.iter().position(|&d| d == b) {
+                counts[i] += 1;
+            }
+        }
+    }
+    // a last line without newline is complete only when the file is
+    if lines.is_empty() && !skip_line {
+        lines.push(counts);
+    }
+
+    // nothing but comments
+    if lines.is_empty() {
+        return b',';
+    }
+    let consistent = (0..DELIMITERS.len())
+        .filter(|&i| lines[0][i] > 0 && lines.iter().all(|line| line[i] == lines[0][i]))
+        .max_by_key(|&i| lines[0][i]);
+    // ragged records still have at least one delimiter on each line
+    let present = || {
+        (0..DELIMITERS.len())
+            .map(|i| {
+                (
+                    i,
+                    lines.iter().map(|line| line[i]).min().unwrap_or_default(),
+                )
+            })
+            .filter(|&(_, min)| min > 0)
+            .max_by_key(|&(_, min)| min)
+            .map(|(i, _)| i)
+    };
+    consistent.or_else(present).map_or(b',', |i| DELIMITERS[i])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_delimiter() {
+        let sniff = |sample: &str| sniff_delimiter(sample.as_bytes(), b'"', Some(b'#')) as char;
+        assert_eq!(sniff("a,b,c\n1,2,3\n"), ',');
+        assert_eq!(sniff("a\tb\n1\t\"x,y,z\"\n"), '\t');
+        assert_eq!(sniff("# a,b,c\na;b\n1;2,5\n"), ';');
+        assert_eq!(sniff("a|b|c"), '|');
+        assert_eq!(sniff("name\nada\n"), ',');
+        // a comma in one line only is data
+        assert_eq!(sniff("a;b\n1,5;2\n3;4\n"), ';');
+        assert_eq!(sniff("1\t2\n3\t4\t5\n"), '\t');
+        assert_eq!(sniff("# only a comment\n"), ',');
+        assert_eq!(sniff("name\n# c\n"), ',');
+    }
+
+    #[test]
+    fn test_complete_headers() {
+        let headers = StringRecord::from(vec!["a"]);
+        assert_eq!(
+            complete_headers(Some(&headers), 3),
+            vec!["a", "col2", "col3"]
+        );
+        assert_eq!(complete_headers(Some(&headers), 0), vec!["a"]);
+        assert_eq!(complete_headers(None, 2), vec!["col1", "col2"]);
+    }
+}
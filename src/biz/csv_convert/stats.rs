@@ -0,0 +1,269 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use chrono::DateTime;
+use csv::StringRecord;
+use serde_json::{json, Map, Value};
+
+use super::filter::compare;
+use super::format::{cell, format_rows};
+use super::{csv_reader, read_table, Columns};
+use crate::{ColumnType, CsvReadOpts, CsvSchemaOpts, CsvStatsOpts};
+
+const SCHEMA: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// A row per column: its type, empty cells, distinct values, min, max and mean
+/// of numbers, the most frequent values and the longest cell.
+pub fn csv_stats(opts: &CsvStatsOpts) -> Result<String> {
+    let (columns, records, rows) = read(&opts.input, &opts.read)?;
+    let stats: Vec<Value> = columns
+        .headers
+        .iter()
+        .zip(&columns.types)
+        .enumerate()
+        .map(|(i, (name, ty))| {
+            let values: Vec<&Value> = rows.iter().map(|row| &row[name]).collect();
+            let longest = records
+                .iter()
+                .map(|record| record.get(i).unwrap_or_default().chars().count())
+                .max();
+            let mut stats = Map::new();
+            stats.insert("column".to_string(), name.into());
+            stats.insert("type".to_string(), type_name(*ty).into());
+            stats.extend(column_stats(&values, opts.top));
+            stats.insert("max_length".to_string(), longest.unwrap_or_default().into());
+            Value::Object(stats)
+        })
+        .collect();
+    format_rows(&stats, opts.format)
+}
+
+/// A JSON Schema of the array of rows `rcli csv` would write.
+pub fn csv_schema(opts: &CsvSchemaOpts) -> Result<Value> {
+    let (columns, _, rows) = read(&opts.input, &opts.read)?;
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for (name, ty) in columns.headers.iter().zip(&columns.types) {
+        let values: Vec<&Value> = rows.iter().map(|row| &row[name]).collect();
+        let mut property = Map::new();
+        let json_type = match ty {
+            Some(ColumnType::Bool) => "boolean",
+            Some(ColumnType::Int) => "integer",
+            Some(ColumnType::Float) => "number",
+            Some(ColumnType::Date) | Some(ColumnType::String) | None => "string",
+        };
+        if values.iter().any(|value| value.is_null()) {
+            property.insert("type".to_string(), json!([json_type, "null"]));
+        } else {
+            property.insert("type".to_string(), json_type.into());
+            required.push(name);
+        }
+        if let Some(ColumnType::Date) = ty {
+            // date-time is RFC 3339 and needs an offset, which times written
+            // as 2024-01-31T08:00:00 lack; a column mixing kinds fits no format
+            let mut formats = values.iter().filter(|value| !value.is_null()).map(|value| {
+                let value = cell(value);
+                match DateTime::parse_from_rfc3339(&value) {
+                    Ok(_) => Some("date-time"),
+                    Err(_) if !value.contains('T') => Some("date"),
+                    Err(_) => None,
+                }
+            });
+            let first = formats.next().flatten();
+            if let Some(format) = first.filter(|&first| formats.all(|f| f == Some(first))) {
+                property.insert("format".to_string(), format.into());
+            }
+        }
+        properties.insert(name.to_string(), Value::Object(property));
+    }
+    Ok(json!({
+        "$schema": SCHEMA,
+        "type": "array",
+        "items": {
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        },
+    }))
+}
+
+fn read(input: &str, opts: &CsvReadOpts) -> Result<(Columns, Vec<StringRecord>, Vec<Value>)> {
+    let mut rdr = csv_reader(input, opts)?;
+    let (columns, records) = read_table(
+        &mut rdr,
+        opts.header,
+        &opts.types,
+        !opts.no_infer,
+        opts.infer_dates,
+    )?;
+    let rows = records
+        .iter()
+        .map(|record| columns.row(record))
+        .collect::<Result<_>>()?;
+    Ok((columns, records, rows))
+}
+
+fn column_stats(values: &[&Value], top: usize) -> Map<String, Value> {
+    // without inference empty cells stay empty strings
+    let present: Vec<&Value> = values
+        .iter()
+        .copied()
+        .filter(|value| !value.is_null() && value.as_str() != Some(""))
+        .collect();
+    let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+    for (i, value) in present.iter().enumerate() {
+        counts.entry(cell(value)).or_insert((0, i)).0 += 1;
+    }
+    let mut frequent: Vec<_> = counts.iter().collect();
+    // ties in the order the values first appear
+    frequent.sort_by(|(_, (a, i)), (_, (b, j))| b.cmp(a).then(i.cmp(j)));
+    let frequent: Vec<String> = frequent
+        .into_iter()
+        .take(top)
+        .map(|(value, (count, _))| format!("{} ({})", value, count))
+        .collect();
+
+    let numbers: Vec<&Value> = present.iter().copied().filter(|v| v.is_number()).collect();
+    let numeric = !numbers.is_empty() && numbers.len() == present.len();
+    let extreme = |ordering: Ordering| {
+        let extreme =
+            numbers
+                .iter()
+                .copied()
+                .reduce(|a, b| match compare(b, a) == Some(ordering) {
+                    true => b,
+                    false => a,
+                });
+        extreme.filter(|_| numeric).cloned().unwrap_or_default()
+    };
+    let mean = numeric.then(|| {
+        let sum: f64 = numbers.iter().filter_map(|v| v.as_f64()).sum();
+        (sum / numbers.len() as f64 * 1e4).round() / 1e4
+    });
+
+    let mut stats = Map::new();
+    stats.insert("nulls".to_string(), (values.len() - present.len()).into());
+    stats.insert(
+        "distinct".to_string(),
+        present
+            .iter()
+            .map(|value| cell(value))
+            .collect::<HashSet<_>>()
+            .len()
+            .into(),
+    );
+    stats.insert("min".to_string(), extreme(Ordering::Less));
+    stats.insert("max".to_string(), extreme(Ordering::Greater));
+    stats.insert("mean".to_string(), mean.into());
+    stats.insert("top".to_string(), frequent.join(", ").into());
+    stats
+}
+
+fn type_name(ty: Option<ColumnType>) -> String {
+    ty.unwrap_or(ColumnType::String).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::{CsvCommand, CsvSubcommand};
+
+    const CSV: &str =
+        "name,age,joined,score\nAda,36,2024-01-31,\nBob,,2024-02-01T08:00:00,x\nAda,40,,y\n";
+
+    fn command(csv: &str, args: &[&str]) -> (tempfile::TempDir, CsvCommand) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.csv");
+        std::fs::write(&input, csv).unwrap();
+        let mut argv = vec!["csv"];
+        argv.extend(&args[..1]);
+        argv.push(input.to_str().unwrap());
+        argv.extend(&args[1..]);
+        let command = CsvCommand::try_parse_from(argv).unwrap();
+        (dir, command)
+    }
+
+    #[test]
+    fn test_csv_stats() {
+        let (_dir, command) = command(CSV, &["stats", "--format", "json", "--top", "1"]);
+        let Some(CsvSubcommand::Stats(opts)) = command.cmd else {
+            panic!("expected stats");
+        };
+        let stats: Value = serde_json::from_str(&csv_stats(&opts).unwrap()).unwrap();
+        assert_eq!(
+            stats[0],
+            json!({
+                "column": "name", "type": "string", "nulls": 0, "distinct": 2,
+                "min": null, "max": null, "mean": null, "top": "Ada (2)", "max_length": 3
+            })
+        );
+        assert_eq!(
+            stats[1],
+            json!({
+                "column": "age", "type": "int", "nulls": 1, "distinct": 2,
+                "min": 36, "max": 40, "mean": 38.0, "top": "36 (1)", "max_length": 2
+            })
+        );
+        assert_eq!(stats[2]["type"], "string");
+        assert_eq!(stats[3]["nulls"], 1);
+
+        let values = [&json!(1.5), &json!(-2), &Value::Null, &json!(1.5)];
+        let stats = column_stats(&values, 5);
+        assert_eq!(
+            (&stats["min"], &stats["max"], &stats["mean"]),
+            (&json!(-2), &json!(1.5), &json!(0.3333))
+        );
+        assert_eq!(stats["top"], "1.5 (2), -2 (1)");
+    }
+
+    #[test]
+    fn test_csv_schema() {
+        let schema = |csv: &str, args: &[&str]| {
+            let (_dir, command) = command(csv, &[&["schema"], args].concat());
+            let Some(CsvSubcommand::Schema(opts)) = command.cmd else {
+                panic!("expected schema");
+            };
+            csv_schema(&opts).unwrap()
+        };
+        assert_eq!(
+            schema(CSV, &["--infer-dates", "--type", "score=string"]),
+            json!({
+                "$schema": SCHEMA,
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "name": {"type": "string"},
+                        "age": {"type": ["integer", "null"]},
+                        // dates with and without a time
+                        "joined": {"type": ["string", "null"]},
+                        "score": {"type": ["string", "null"]}
+                    },
+                    "required": ["name"]
+                }
+            })
+        );
+
+        let dates = "d,t,z\n2024-01-31,2024-01-31T08:00:00,2024-01-31T08:00:00Z\n,2024-02-01 09:30:00,2024-02-01T09:30:00+01:00\n";
+        let properties = &schema(dates, &["--infer-dates"])["items"]["properties"];
+        assert_eq!(
+            properties["d"],
+            json!({"type": ["string", "null"], "format": "date"})
+        );
+        // times without an offset are not RFC 3339
+        assert_eq!(properties["t"], json!({"type": "string"}));
+        assert_eq!(
+            properties["z"],
+            json!({"type": "string", "format": "date-time"})
+        );
+
+        // without a subcommand the options convert
+        let command = CsvCommand::try_parse_from(["csv", "-i", "-", "--limit", "1"]).unwrap();
+        assert!(command.cmd.is_none());
+        assert_eq!(command.convert.unwrap().limit, Some(1));
+        assert!(CsvCommand::try_parse_from(["csv", "-i", "-", "stats", "-"]).is_err());
+    }
+}
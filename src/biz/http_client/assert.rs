@@ -0,0 +1,275 @@
+use std::fmt;
+use std::str::FromStr;
+
+use axum::http::{HeaderMap, StatusCode};
+use serde_json::Value;
+
+/// A `# @assert <subject> <op> [value]` check on the response, e.g.
+/// `status == 200`, `header content-type contains json`, `body contains ok`
+/// or `json /items/0/id == 7`.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Assertion {
+    subject: Subject,
+    op: Op,
+    expected: String,
+    source: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Subject {
+    Status,
+    Header(String),
+    Body,
+    /// A JSON pointer into the response body.
+    Json(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    Exists,
+}
+
+/// The parts of a response assertions look at.
+#[derive(Debug)]
+pub(super) struct Received<'a> {
+    pub status: StatusCode,
+    pub headers: &'a HeaderMap,
+    pub body: &'a [u8],
+}
+
+#[derive(Debug)]
+enum Actual {
+    Missing,
+    Text(String),
+    Json(Value),
+}
+
+impl FromStr for Assertion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let source = s.trim().to_string();
+        let (subject, rest) = split_word(&source);
+        let (subject, rest) = match subject {
+            "status" => (Subject::Status, rest),
+            "body" => (Subject::Body, rest),
+            "header" | "json" => {
+                let (arg, rest) = split_word(rest);
+                if arg.is_empty() {
+                    return Err(format!("{} needs a name in @assert {}", subject, source));
+                }
+                if subject == "header" {
+                    (Subject::Header(arg.to_lowercase()), rest)
+                } else {
+                    (Subject::Json(arg.to_string()), rest)
+                }
+            }
+            _ => return Err(format!("Unknown subject in @assert {}", source)),
+        };
+        let (op, expected) = split_word(rest);
+        let op = match op {
+            "==" => Op::Eq,
+            "!=" => Op::Ne,
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            "contains" => Op::Contains,
+            "exists" => Op::Exists,
+            _ => return Err(format!("Unknown operator in @assert {}", source)),
+        };
+        if (op == Op::Exists) != expected.is_empty() {
+            return Err(format!("Wrong number of values in @assert {}", source));
+        }
+        Ok(Self {
+            subject,
+            op,
+            expected: unquote(expected).to_string(),
+            source,
+        })
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Assertion {
+    /// `Err` describes what was received instead.
+    pub fn check(&self, received: &Received) -> Result<(), String> {
+        let actual = self.actual(received);
+        let ok = match (&actual, self.op) {
+            (Actual::Missing, Op::Ne) => true,
+            (Actual::Missing, _) => false,
+            (_, Op::Exists) => true,
+            (actual, Op::Eq) => self.equals(actual),
+            (actual, Op::Ne) => !self.equals(actual),
+            (actual, Op::Contains) => self.contains(actual),
+            (actual, op) => match (number(actual), self.expected.parse::<f64>()) {
+                (Some(actual), Ok(expected)) => match op {
+                    Op::Lt => actual < expected,
+                    Op::Le => actual <= expected,
+                    Op::Gt => actual > expected,
+                    _ => actual >= expected,
+                },
+                _ => false,
+            },
+        };
+        if ok {
+            return Ok(());
+        }
+        Err(match actual {
+            Actual::Missing => "missing".to_string(),
+            Actual::Text(text) => format!("got {}", abbreviate(&text)),
+            Actual::Json(value) => format!("got {}", abbreviate(&value.to_string())),
+        })
+    }
+
+    fn actual(&self, received: &Received) -> Actual {
+        match &self.subject {
+            Subject::Status => Actual::Json(received.status.as_u16().into()),
+            Subject::Header(name) => {
+                let values: Vec<_> = received
+                    .headers
+                    .get_all(name)
+                    .iter()
+                    .map(|v| String::from_utf8_lossy(v.as_bytes()).to_string())
+                    .collect();
+                if values.is_empty() {
+                    Actual::Missing
+                } else {
+                    Actual::Text(values.join(", "))
+                }
+            }
+            Subject::Body => Actual::Text(String::from_utf8_lossy(received.body).to_string()),
+            Subject::Json(pointer) => serde_json::from_slice::<Value>(received.body)
+                .ok()
+                .and_then(|json| json.pointer(pointer).cloned())
+                .map_or(Actual::Missing, Actual::Json),
+        }
+    }
+
+    fn expected_json(&self) -> Value {
+        serde_json::from_str(&self.expected)
+            .unwrap_or_else(|_| Value::String(self.expected.clone()))
+    }
+
+    fn equals(&self, actual: &Actual) -> bool {
+        match actual {
+            Actual::Missing => false,
+            Actual::Text(text) => *text == self.expected,
+            Actual::Json(value) => *value == self.expected_json(),
+        }
+    }
+
+    fn contains(&self, actual: &Actual) -> bool {
+        match actual {
+            Actual::Missing => false,
+            Actual::Text(text) | Actual::Json(Value::String(text)) => text.contains(&self.expected),
+            Actual::Json(Value::Array(items)) => items.contains(&self.expected_json()),
+            Actual::Json(Value::Object(map)) => map.contains_key(&self.expected),
+            Actual::Json(other) => other.to_string().contains(&self.expected),
+        }
+    }
+}
+
+fn number(actual: &Actual) -> Option<f64> {
+    match actual {
+        Actual::Json(value) => value.as_f64(),
+        Actual::Text(text) => text.trim().parse().ok(),
+        Actual::Missing => None,
+    }
+}
+
+fn split_word(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    match s.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (s, ""),
+    }
+}
+
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .filter(|inner| !inner.contains('"'))
+        .unwrap_or(s)
+}
+
+fn abbreviate(s: &str) -> String {
+    const MAX: usize = 80;
+    match s.char_indices().nth(MAX) {
+        Some((end, _)) => format!("{}…", &s[..end]),
+        None => s.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header;
+
+    use super::*;
+
+    fn check(assertion: &str, status: u16, body: &str) -> Result<(), String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+        let assertion: Assertion = assertion.parse().unwrap();
+        assertion.check(&Received {
+            status: StatusCode::from_u16(status).unwrap(),
+            headers: &headers,
+            body: body.as_bytes(),
+        })
+    }
+
+    #[test]
+    fn test_parse_assertion() {
+        for bad in [
+            "",
+            "code == 200",
+            "status ~ 200",
+            "status ==",
+            "header",
+            "header x exists 1",
+        ] {
+            assert!(bad.parse::<Assertion>().is_err(), "{}", bad);
+        }
+        let assertion: Assertion = " body contains \"a b\" ".parse().unwrap();
+        assert_eq!(assertion.expected, "a b");
+        assert_eq!(assertion.to_string(), "body contains \"a b\"");
+    }
+
+    #[test]
+    fn test_check() {
+        let body = r#"{"id": 7, "name": "rcli", "tags": ["cli", "http"]}"#;
+        assert!(check("status == 200", 200, body).is_ok());
+        assert!(check("status < 400", 302, body).is_ok());
+        assert_eq!(
+            check("status == 200", 404, body),
+            Err("got 404".to_string())
+        );
+        assert!(check("header Content-Type contains json", 200, body).is_ok());
+        assert!(check("header etag exists", 200, body).is_err());
+        assert!(check("header etag != x", 200, body).is_ok());
+        assert!(check("body contains rcli", 200, body).is_ok());
+        assert!(check("json /id == 7", 200, body).is_ok());
+        assert!(check("json /id >= 7", 200, body).is_ok());
+        assert!(check("json /name == rcli", 200, body).is_ok());
+        assert!(check("json /name == \"rcli\"", 200, body).is_ok());
+        assert!(check("json /tags contains \"http\"", 200, body).is_ok());
+        assert!(check("json /tags/0 == cli", 200, body).is_ok());
+        assert_eq!(
+            check("json /missing exists", 200, body),
+            Err("missing".to_string())
+        );
+        assert!(check("json /id == 7", 200, "not json").is_err());
+    }
+}
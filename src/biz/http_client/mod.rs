@@ -0,0 +1,337 @@
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, Context, Result};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method};
+use colored::Colorize;
+
+use assert::{Assertion, Received};
+use parse::{load_env, parse_http_file, BodySpec, RequestSpec, Variables};
+
+use crate::HttpRequestOpts;
+
+mod assert;
+mod parse;
+
+// longer bodies are cut short when printed
+const MAX_PRINTED: usize = 64 << 10;
+
+/// Send the requests of a `.http` file in order and print the responses.
+///
+/// A request passes when a response arrives and all its `# @assert`s hold;
+/// the command fails if any request did not pass, so it can gate CI.
+pub async fn http_request(opts: HttpRequestOpts) -> Result<()> {
+    let path = Path::new(&opts.file);
+    let base = path.parent().unwrap_or(Path::new("."));
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", opts.file))?;
+    let file = parse_http_file(&content, base).with_context(|| format!("Invalid {}", opts.file))?;
+
+    // later sources win: environment, then file variables, then --var
+    let mut vars = Variables::default();
+    if let Some(env) = &opts.env {
+        vars.extend(environment(opts.env_file.as_deref(), base, env)?);
+    }
+    vars.extend(file.variables);
+    vars.extend(opts.vars);
+
+    let requests: Vec<_> = file
+        .requests
+        .iter()
+        .filter(|spec| {
+            let Some(filter) = &opts.filter else {
+                return true;
+            };
+            [&spec.name, &spec.title]
+                .iter()
+                .any(|label| label.as_ref().is_some_and(|l| l.contains(filter)))
+        })
+        .collect();
+    if requests.is_empty() {
+        bail!("No requests to send in {}", opts.file);
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(opts.timeout)
+        .danger_accept_invalid_certs(opts.insecure)
+        .build()?;
+    let mut failed = 0;
+    for spec in &requests {
+        if !run(&client, spec, &vars, opts.quiet).await {
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        bail!("{} of {} requests failed", failed, requests.len());
+    }
+    println!(
+        "{}",
+        format!("All {} requests passed", requests.len()).green()
+    );
+    Ok(())
+}
+
+/// Variables of `env`, from `env_file` or the env files next to the `.http`
+/// file, where `http-client.private.env.json` overrides the shared one.
+fn environment(env_file: Option<&str>, base: &Path, env: &str) -> Result<Vec<(String, String)>> {
+    let files = match env_file {
+        Some(file) => vec![Path::new(file).to_path_buf()],
+        None => ["http-client.env.json", "http-client.private.env.json"]
+            .iter()
+            .map(|name| base.join(name))
+            .filter(|path| path.exists())
+            .collect(),
+    };
+    let mut vars = Vec::new();
+    let mut found = false;
+    for file in &files {
+        if let Some(env_vars) = load_env(file, env)? {
+            vars.extend(env_vars);
+            found = true;
+        }
+    }
+    if !found {
+        bail!("Environment {} not found in {:?}", env, files);
+    }
+    Ok(vars)
+}
+
+/// Send one request and report on it; `true` when it passed.
+async fn run(client: &reqwest::Client, spec: &RequestSpec, vars: &Variables, quiet: bool) -> bool {
+    println!("{} {}", "###".blue(), spec.label().bold());
+    let request = match build(client, spec, vars) {
+        Ok(request) => request,
+        Err(e) => {
+            println!("{} line {}: {:#}\n", "✗".red(), spec.line, e);
+            return false;
+        }
+    };
+    println!("{} {}", request.method(), request.url());
+
+    let started = Instant::now();
+    let response = match client.execute(request).await {
+        Ok(response) => response,
+        Err(e) => {
+            println!("{} {}\n", "✗".red(), error_chain(&e));
+            return false;
+        }
+    };
+    let status = response.status();
+    let version = response.version();
+    let headers = response.headers().clone();
+    let body = match response.bytes().await {
+        Ok(body) => body,
+        Err(e) => {
+            println!("{} {}\n", "✗".red(), error_chain(&e));
+            return false;
+        }
+    };
+    let status_line = format!(
+        "{:?} {} ({} ms)",
+        version,
+        status,
+        started.elapsed().as_millis()
+    );
+    if status.is_client_error() || status.is_server_error() {
+        println!("{}", status_line.red());
+    } else {
+        println!("{}", status_line.green());
+    }
+    if !quiet {
+        for (name, value) in &headers {
+            println!(
+                "{}: {}",
+                name.as_str().cyan(),
+                String::from_utf8_lossy(value.as_bytes())
+            );
+        }
+        println!();
+        print_body(&headers, &body);
+    }
+
+    let received = Received {
+        status,
+        headers: &headers,
+        body: &body,
+    };
+    let mut passed = true;
+    for assertion in &spec.assertions {
+        // values may use variables, e.g. `json /id == {{user}}`
+        let assertion = match vars
+            .substitute(&assertion.to_string())
+            .map_err(|e| e.to_string())
+            .and_then(|source| source.parse::<Assertion>())
+        {
+            Ok(assertion) => assertion,
+            Err(e) => {
+                println!("{} {} ({})", "✗".red(), assertion, e);
+                passed = false;
+                continue;
+            }
+        };
+        match assertion.check(&received) {
+            Ok(()) => println!("{} {}", "✓".green(), assertion),
+            Err(actual) => {
+                println!("{} {} ({})", "✗".red(), assertion, actual);
+                passed = false;
+            }
+        }
+    }
+    println!();
+    passed
+}
+
+fn build(
+    client: &reqwest::Client,
+    spec: &RequestSpec,
+    vars: &Variables,
+) -> Result<reqwest::Request> {
+    let method = Method::from_bytes(spec.method.as_bytes())?;
+    let url = vars.substitute(&spec.url)?;
+    let url = reqwest::Url::parse(&url).map_err(|e| anyhow!("Invalid url {}: {}", url, e))?;
+    let mut headers = HeaderMap::new();
+    for (name, value) in &spec.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| anyhow!("Invalid header name {}", name))?;
+        let value = HeaderValue::from_str(&vars.substitute(value)?)
+            .map_err(|_| anyhow!("Invalid value for header {}", name))?;
+        headers.append(name, value);
+    }
+    let mut request = client.request(method, url).headers(headers);
+    match &spec.body {
+        Some(BodySpec::Text(text)) => request = request.body(vars.substitute(text)?),
+        Some(BodySpec::File(path)) => {
+            let body = std::fs::read(path)
+                .with_context(|| format!("Failed to read body {}", path.display()))?;
+            request = request.body(body);
+        }
+        None => {}
+    }
+    Ok(request.build()?)
+}
+
+fn print_body(headers: &HeaderMap, body: &[u8]) {
+    if body.is_empty() {
+        return;
+    }
+    let Ok(text) = std::str::from_utf8(body) else {
+        println!(
+            "{}\n",
+            format!("<{} bytes of binary data>", body.len()).dimmed()
+        );
+        return;
+    };
+    let is_json = headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("json"));
+    let text = match is_json {
+        true => jsonxf::pretty_print(text).unwrap_or_else(|_| text.to_string()),
+        false => text.to_string(),
+    };
+    match text.char_indices().nth(MAX_PRINTED) {
+        Some((end, _)) => println!(
+            "{}\n{}\n",
+            &text[..end],
+            format!("<{} more bytes>", text.len() - end).dimmed()
+        ),
+        None => println!("{}\n", text.trim_end()),
+    }
+}
+
+/// reqwest hides the interesting part, e.g. connection refused, in sources.
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        message.push_str(": ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::extract::Path as UrlPath;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use clap::Parser;
+
+    use super::*;
+
+    async fn server() -> SocketAddr {
+        let app = Router::new()
+            .route(
+                "/users/:id",
+                get(|UrlPath(id): UrlPath<u32>| async move {
+                    Json(serde_json::json!({"id": id, "name": "rcli"}))
+                }),
+            )
+            .route("/echo", post(|body: String| async move { body }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    fn opts(file: &Path, args: &[&str]) -> HttpRequestOpts {
+        let file = file.to_str().unwrap();
+        HttpRequestOpts::try_parse_from(["request", file].iter().chain(args)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_http_request() {
+        let addr = server().await;
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("http-client.env.json"),
+            r#"{"dev": {"host": "localhost", "user": 1}}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("http-client.private.env.json"),
+            r#"{"dev": {"host": "127.0.0.1"}}"#,
+        )
+        .unwrap();
+        let file = dir.path().join("smoke.http");
+        std::fs::write(
+            &file,
+            "@base = http://{{host}}:{{port}}\n\
+             ### user\n\
+             # @assert status == 200\n\
+             # @assert header content-type contains json\n\
+             # @assert json /id == {{user}}\n\
+             GET {{base}}/users/7\n\
+             \n\
+             ### echo\n\
+             # @name echo\n\
+             # @assert body == hello 7\n\
+             POST {{base}}/echo\n\
+             Content-Type: text/plain\n\
+             \n\
+             hello {{user}}\n",
+        )
+        .unwrap();
+        let port = format!("port={}", addr.port());
+
+        // user=1 from the env file fails both assertions on it
+        let result = http_request(opts(&file, &["--env", "dev", "--var", &port])).await;
+        assert_eq!(result.unwrap_err().to_string(), "2 of 2 requests failed");
+        let passing = ["--env", "dev", "--var", &port, "--var", "user=7", "-q"];
+        http_request(opts(&file, &passing)).await.unwrap();
+
+        let only_echo = [
+            "--env", "dev", "--var", &port, "--var", "user=7", "-f", "echo",
+        ];
+        http_request(opts(&file, &only_echo)).await.unwrap();
+        let nothing = ["--env", "dev", "--var", &port, "-f", "nothing"];
+        assert!(http_request(opts(&file, &nothing)).await.is_err());
+
+        assert!(http_request(opts(&file, &["--env", "prod"])).await.is_err());
+        // `port` is undefined without --var
+        assert!(http_request(opts(&file, &["--env", "dev"])).await.is_err());
+    }
+}
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+use rand::Rng;
+
+use super::assert::Assertion;
+
+const METHODS: &[&str] = &[
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+];
+
+// variables may refer to other variables, but not endlessly
+const MAX_DEPTH: usize = 10;
+
+/// A request as written in the `.http` file, before variables are resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct RequestSpec {
+    /// The text after `###`.
+    pub title: Option<String>,
+    /// From a `# @name` comment.
+    pub name: Option<String>,
+    /// 1-based line of the request line.
+    pub line: usize,
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<BodySpec>,
+    pub assertions: Vec<Assertion>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum BodySpec {
+    Text(String),
+    /// `< ./payload.json`, relative to the `.http` file.
+    File(PathBuf),
+}
+
+#[derive(Debug, Default)]
+pub(super) struct HttpFile {
+    /// `@name = value` definitions, in file order.
+    pub variables: Vec<(String, String)>,
+    pub requests: Vec<RequestSpec>,
+}
+
+impl RequestSpec {
+    /// How the request is referred to in output: its name, title or line.
+    pub fn label(&self) -> String {
+        self.name
+            .clone()
+            .or_else(|| self.title.clone())
+            .unwrap_or_else(|| format!("{} {}", self.method, self.url))
+    }
+}
+
+/// Parse the JetBrains / VS Code REST Client format.
+///
+/// Requests are separated by `###` lines. Before the request line come
+/// comments (`#` or `//`), `# @name` and `# @assert` directives and
+/// `@variable = value` definitions; after it the headers, a blank line and
+/// the body. `base` is where `< file` bodies are looked up.
+pub(super) fn parse_http_file(content: &str, base: &Path) -> Result<HttpFile> {
+    let mut file = HttpFile::default();
+    let mut block: Vec<(usize, &str)> = Vec::new();
+    let mut title = None;
+    for (n, line) in content.lines().enumerate() {
+        if let Some(rest) = line.strip_prefix("###") {
+            parse_block(&block, title.take(), base, &mut file)?;
+            block.clear();
+            title = Some(rest.trim().to_string()).filter(|t| !t.is_empty());
+        } else {
+            block.push((n + 1, line));
+        }
+    }
+    parse_block(&block, title, base, &mut file)?;
+    Ok(file)
+}
+
+fn parse_block(
+    lines: &[(usize, &str)],
+    title: Option<String>,
+    base: &Path,
+    file: &mut HttpFile,
+) -> Result<()> {
+    let mut name = None;
+    let mut assertions = Vec::new();
+    let mut lines = lines.iter().peekable();
+
+    // preamble: comments, directives and variables up to the request line
+    let (line, request_line) = loop {
+        let Some(&(n, line)) = lines.next() else {
+            // a block of only comments or variables
+            return Ok(());
+        };
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if let Some(comment) = comment(trimmed) {
+            if let Some(value) = comment.strip_prefix("@name") {
+                name = Some(value.trim().to_string()).filter(|v| !v.is_empty());
+            } else if let Some(assertion) = comment.strip_prefix("@assert") {
+                let assertion = assertion
+                    .parse()
+                    .map_err(|e| anyhow!("line {}: {}", n, e))?;
+                assertions.push(assertion);
+            }
+            continue;
+        }
+        if let Some(definition) = trimmed.strip_prefix('@') {
+            let (key, value) = definition
+                .split_once('=')
+                .ok_or_else(|| anyhow!("line {}: expected @name = value", n))?;
+            file.variables
+                .push((key.trim().to_string(), value.trim().to_string()));
+            continue;
+        }
+        break (n, trimmed);
+    };
+
+    let mut parts = request_line.split_whitespace();
+    let first = parts.next().unwrap_or_default();
+    let (method, mut url) = if METHODS.contains(&first) {
+        let url = parts
+            .next()
+            .ok_or_else(|| anyhow!("line {}: missing url", line))?;
+        (first.to_string(), url.to_string())
+    } else {
+        ("GET".to_string(), first.to_string())
+    };
+    if let Some(extra) = parts.next().filter(|p| !p.starts_with("HTTP/")) {
+        bail!("line {}: unexpected {} after the url", line, extra);
+    }
+    // long query strings may continue on indented lines
+    while let Some(&&(n, next)) = lines.peek() {
+        let trimmed = next.trim_start();
+        if next.len() == trimmed.len() || !(trimmed.starts_with('?') || trimmed.starts_with('&')) {
+            break;
+        }
+        let mut parts = trimmed.split_whitespace();
+        url.push_str(parts.next().unwrap_or_default());
+        if let Some(extra) = parts.next().filter(|p| !p.starts_with("HTTP/")) {
+            bail!("line {}: unexpected {} after the url", n, extra);
+        }
+        lines.next();
+    }
+
+    let mut headers = Vec::new();
+    for &(n, line) in lines.by_ref() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            break;
+        }
+        if comment(trimmed).is_some() {
+            continue;
+        }
+        let (key, value) = trimmed
+            .split_once(':')
+            .ok_or_else(|| anyhow!("line {}: expected a header, Name: value", n))?;
+        headers.push((key.trim().to_string(), value.trim().to_string()));
+    }
+
+    let body_lines: Vec<&str> = lines
+        .map(|(_, line)| *line)
+        // response handlers and references are IDE features
+        .filter(|line| !line.starts_with("<> ") && !line.starts_with("> "))
+        .collect();
+    let body = body_lines.join("\n");
+    let body = body.trim_end();
+    let body = if body.trim().is_empty() {
+        None
+    } else if let Some(path) = body.trim_start().strip_prefix("< ") {
+        Some(BodySpec::File(base.join(path.trim())))
+    } else {
+        Some(BodySpec::Text(body.to_string()))
+    };
+
+    file.requests.push(RequestSpec {
+        title,
+        name,
+        line,
+        method,
+        url,
+        headers,
+        body,
+        assertions,
+    });
+    Ok(())
+}
+
+fn comment(line: &str) -> Option<&str> {
+    line.strip_prefix('#')
+        .or_else(|| line.strip_prefix("//"))
+        .map(str::trim)
+}
+
+/// Variable values, later sources overriding earlier ones.
+#[derive(Debug, Default)]
+pub(super) struct Variables(HashMap<String, String>);
+
+impl Variables {
+    pub fn extend(&mut self, vars: impl IntoIterator<Item = (String, String)>) {
+        self.0.extend(vars);
+    }
+
+    /// Replace `{{name}}` and the dynamic `{{$uuid}}`, `{{$timestamp}}`,
+    /// `{{$isoTimestamp}}` and `{{$randomInt}}`.
+    pub fn substitute(&self, text: &str) -> Result<String> {
+        self.substitute_at(text, 0)
+    }
+
+    fn substitute_at(&self, text: &str, depth: usize) -> Result<String> {
+        if depth > MAX_DEPTH {
+            bail!("Variables nested too deeply in {}", text);
+        }
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start..].find("}}") else {
+                break;
+            };
+            out.push_str(&rest[..start]);
+            let name = rest[start + 2..start + len].trim();
+            let value = match name {
+                "$uuid" => uuid_v4(),
+                "$timestamp" => Utc::now().timestamp().to_string(),
+                "$isoTimestamp" => Utc::now().to_rfc3339(),
+                "$randomInt" => rand::thread_rng().gen_range(0..1000).to_string(),
+                _ => {
+                    let value = self
+                        .0
+                        .get(name)
+                        .ok_or_else(|| anyhow!("Unknown variable {{{{{}}}}}", name))?;
+                    self.substitute_at(value, depth + 1)?
+                }
+            };
+            out.push_str(&value);
+            rest = &rest[start + len + 2..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+/// Load `env` from a JetBrains style env file: `{"dev": {"host": "..."}}`,
+/// with `$shared` values applying to every environment. `None` when the file
+/// does not define `env`.
+pub(super) fn load_env(path: &Path, env: &str) -> Result<Option<Vec<(String, String)>>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+    let envs: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&content)
+        .map_err(|e| anyhow!("Invalid env file {}: {}", path.display(), e))?;
+    if !envs.contains_key(env) {
+        return Ok(None);
+    }
+    let mut vars = Vec::new();
+    for key in ["$shared", env] {
+        let Some(values) = envs.get(key) else {
+            continue;
+        };
+        let values = values
+            .as_object()
+            .ok_or_else(|| anyhow!("{} in {} is not an object", key, path.display()))?;
+        for (name, value) in values {
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            vars.push((name.clone(), value));
+        }
+    }
+    Ok(Some(vars))
+}
+
+fn uuid_v4() -> String {
+    let mut bytes: [u8; 16] = rand::thread_rng().gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = r#"@host = localhost:8080
+@base = http://{{host}}
+
+### list users
+# @name users
+# @assert status == 200
+GET {{base}}/users
+    ?page=2
+    &size=10 HTTP/1.1
+Accept: application/json
+// a comment
+
+###
+POST {{base}}/users
+Content-Type: application/json
+
+{
+  "name": "{{$uuid}}"
+}
+
+> {% client.test("ok", () => {}); %}
+
+### only a comment
+
+###
+PUT http://example.com/upload
+Content-Type: application/octet-stream
+
+< ./data.bin
+
+###
+http://example.com/plain
+"#;
+
+    #[test]
+    fn test_parse_http_file() {
+        let file = parse_http_file(FILE, Path::new("/tmp")).unwrap();
+        assert_eq!(
+            file.variables,
+            [
+                ("host".to_string(), "localhost:8080".to_string()),
+                ("base".to_string(), "http://{{host}}".to_string())
+            ]
+        );
+        assert_eq!(file.requests.len(), 4);
+
+        let users = &file.requests[0];
+        assert_eq!(users.title.as_deref(), Some("list users"));
+        assert_eq!(users.name.as_deref(), Some("users"));
+        assert_eq!(users.label(), "users");
+        assert_eq!(users.line, 7);
+        assert_eq!(users.method, "GET");
+        assert_eq!(users.url, "{{base}}/users?page=2&size=10");
+        assert_eq!(
+            users.headers,
+            [("Accept".to_string(), "application/json".to_string())]
+        );
+        assert_eq!(users.body, None);
+        assert_eq!(users.assertions.len(), 1);
+
+        let create = &file.requests[1];
+        assert_eq!(create.method, "POST");
+        assert_eq!(
+            create.body,
+            Some(BodySpec::Text(
+                "{\n  \"name\": \"{{$uuid}}\"\n}".to_string()
+            ))
+        );
+
+        assert_eq!(
+            file.requests[2].body,
+            Some(BodySpec::File(PathBuf::from("/tmp/./data.bin")))
+        );
+        assert_eq!(file.requests[3].method, "GET");
+        assert_eq!(file.requests[3].url, "http://example.com/plain");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_http_file("GET", Path::new(".")).is_err());
+        assert!(parse_http_file("GET http://a b", Path::new(".")).is_err());
+        assert!(parse_http_file("GET http://a\nnot a header", Path::new(".")).is_err());
+        assert!(parse_http_file("@novalue\nGET http://a", Path::new(".")).is_err());
+        assert!(parse_http_file("# @assert status ~ 1\nGET http://a", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_parse_repo_test_http() {
+        let content = std::fs::read_to_string("test.http").unwrap();
+        let file = parse_http_file(&content, Path::new(".")).unwrap();
+        assert!(!file.requests.is_empty());
+        assert!(file.requests.iter().all(|r| r.url.starts_with("http")));
+    }
+
+    #[test]
+    fn test_substitute() {
+        let mut vars = Variables::default();
+        vars.extend([
+            ("host".to_string(), "localhost".to_string()),
+            ("base".to_string(), "http://{{ host }}".to_string()),
+        ]);
+        assert_eq!(
+            vars.substitute("{{base}}/a?x={{host}}").unwrap(),
+            "http://localhost/a?x=localhost"
+        );
+        assert!(vars.substitute("{{missing}}").is_err());
+        assert_eq!(vars.substitute("{{ unclosed").unwrap(), "{{ unclosed");
+
+        let uuid = vars.substitute("{{$uuid}}").unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert!(vars
+            .substitute("{{$timestamp}}")
+            .unwrap()
+            .parse::<i64>()
+            .is_ok());
+
+        vars.extend([("loop".to_string(), "{{loop}}".to_string())]);
+        assert!(vars.substitute("{{loop}}").is_err());
+    }
+
+    #[test]
+    fn test_load_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("http-client.env.json");
+        std::fs::write(
+            &path,
+            r#"{"$shared": {"version": "v1"}, "dev": {"host": "localhost", "port": 8080}}"#,
+        )
+        .unwrap();
+        let vars = load_env(&path, "dev").unwrap().unwrap();
+        assert!(vars.contains(&("version".to_string(), "v1".to_string())));
+        assert!(vars.contains(&("port".to_string(), "8080".to_string())));
+        assert!(load_env(&path, "prod").unwrap().is_none());
+        std::fs::write(&path, r#"{"dev": "localhost"}"#).unwrap();
+        assert!(load_env(&path, "dev").is_err());
+    }
+}
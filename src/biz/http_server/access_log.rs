@@ -0,0 +1,231 @@
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{LineWriter, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use axum::body::{Body, HttpBody};
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{header, HeaderMap, Response};
+use axum::middleware::Next;
+use chrono::{DateTime, Local};
+use log::warn;
+
+use super::auth::AuthUser;
+use crate::AccessLogFormat;
+
+/// Destination of `--access-log`.
+pub(super) struct AccessLog {
+    format: AccessLogFormat,
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+/// Everything logged about one request.
+#[derive(Debug)]
+struct Entry {
+    remote: Option<SocketAddr>,
+    user: Option<String>,
+    time: DateTime<Local>,
+    method: String,
+    target: String,
+    version: String,
+    status: u16,
+    bytes: Option<u64>,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    duration_ms: u128,
+}
+
+impl AccessLog {
+    /// Open `path` for appending, `-` logs to stdout.
+    pub fn open(path: &str, format: AccessLogFormat) -> Result<Self> {
+        let writer: Box<dyn Write + Send> = if path == "-" {
+            Box::new(std::io::stdout())
+        } else {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open access log {}", path))?;
+            Box::new(LineWriter::new(file))
+        };
+        Ok(Self {
+            format,
+            writer: Mutex::new(writer),
+        })
+    }
+
+    fn write(&self, entry: &Entry) {
+        let line = entry.format(self.format);
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(writer, "{}", line) {
+            warn!("Failed to write access log: {}", e);
+        }
+    }
+}
+
+impl fmt::Debug for AccessLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessLog")
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Entry {
+    fn format(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Common => self.common(),
+            AccessLogFormat::Combined => format!(
+                "{} \"{}\" \"{}\"",
+                self.common(),
+                quote(self.referer.as_deref()),
+                quote(self.user_agent.as_deref())
+            ),
+            AccessLogFormat::Json => serde_json::json!({
+                "time": self.time.to_rfc3339(),
+                "remote": self.remote.map(|addr| addr.ip().to_string()),
+                "user": self.user,
+                "method": self.method,
+                "target": self.target,
+                "version": self.version,
+                "status": self.status,
+                "bytes": self.bytes,
+                "referer": self.referer,
+                "user_agent": self.user_agent,
+                "duration_ms": self.duration_ms,
+            })
+            .to_string(),
+        }
+    }
+
+    /// `host ident authuser [date] "request" status bytes`
+    fn common(&self) -> String {
+        format!(
+            "{} - {} [{}] \"{} {} {}\" {} {}",
+            self.remote
+                .map_or_else(|| "-".to_string(), |addr| addr.ip().to_string()),
+            self.user.as_deref().unwrap_or("-"),
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            self.method,
+            quote(Some(&self.target)),
+            self.version,
+            self.status,
+            self.bytes
+                .map_or_else(|| "-".to_string(), |b| b.to_string())
+        )
+    }
+}
+
+/// Middleware writing one access log line per request, once the response
+/// head is ready.
+pub(super) async fn access_log(
+    State(log): State<Arc<AccessLog>>,
+    req: Request,
+    next: Next,
+) -> Response<Body> {
+    let started = Instant::now();
+    let time = Local::now();
+    let remote = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    let method = req.method().to_string();
+    let target = req
+        .uri()
+        .path_and_query()
+        .map_or_else(|| req.uri().path().to_string(), |pq| pq.to_string());
+    let version = format!("{:?}", req.version());
+    let referer = header_value(req.headers(), header::REFERER);
+    let user_agent = header_value(req.headers(), header::USER_AGENT);
+
+    let response = next.run(req).await;
+
+    let bytes = header_value(response.headers(), header::CONTENT_LENGTH)
+        .and_then(|v| v.parse().ok())
+        .or_else(|| response.body().size_hint().exact());
+    log.write(&Entry {
+        remote,
+        user: response.extensions().get::<AuthUser>().map(|u| u.0.clone()),
+        time,
+        method,
+        target,
+        version,
+        status: response.status().as_u16(),
+        bytes,
+        referer,
+        user_agent,
+        duration_ms: started.elapsed().as_millis(),
+    });
+    response
+}
+
+fn header_value(headers: &HeaderMap, name: header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Escape a value for a double quoted log field, `-` when absent.
+fn quote(value: Option<&str>) -> String {
+    match value {
+        Some(value) => value.replace('\\', "\\\\").replace('"', "\\\""),
+        None => "-".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            remote: Some("127.0.0.1:50000".parse().unwrap()),
+            user: None,
+            time: Local.with_ymd_and_hms(2024, 6, 1, 12, 30, 5).unwrap(),
+            method: "GET".to_string(),
+            target: "/a.txt?x=1".to_string(),
+            version: "HTTP/1.1".to_string(),
+            status: 200,
+            bytes: Some(42),
+            referer: None,
+            user_agent: Some("curl/8.0 \"quoted\"".to_string()),
+            duration_ms: 3,
+        }
+    }
+
+    #[test]
+    fn test_format_entry() {
+        let entry = entry();
+        let common = entry.format(AccessLogFormat::Common);
+        assert!(common.starts_with("127.0.0.1 - - [01/Jun/2024:12:30:05 "));
+        assert!(common.ends_with("] \"GET /a.txt?x=1 HTTP/1.1\" 200 42"));
+
+        let combined = entry.format(AccessLogFormat::Combined);
+        assert!(combined.starts_with(&common));
+        assert!(combined.ends_with(" \"-\" \"curl/8.0 \\\"quoted\\\"\""));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&entry.format(AccessLogFormat::Json)).unwrap();
+        assert_eq!(json["remote"], "127.0.0.1");
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["bytes"], 42);
+        assert_eq!(json["referer"], serde_json::Value::Null);
+
+        let entry = Entry {
+            user: Some("alice".to_string()),
+            ..entry
+        };
+        assert!(entry
+            .format(AccessLogFormat::Common)
+            .starts_with("127.0.0.1 - alice ["));
+        let json: serde_json::Value =
+            serde_json::from_str(&entry.format(AccessLogFormat::Json)).unwrap();
+        assert_eq!(json["user"], "alice");
+    }
+}
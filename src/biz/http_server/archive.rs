@@ -0,0 +1,322 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use axum::body::Body;
+use axum::http::{header, Response, StatusCode};
+use chrono::{DateTime, Datelike, Local, Timelike};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{info, warn};
+use tokio_util::io::{ReaderStream, SyncIoBridge};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use super::file::content_disposition;
+use super::listing::{read_entries, ListingOptions, SortKey};
+
+// bytes buffered between the archive writer and the response body
+const PIPE_SIZE: usize = 64 << 10;
+
+/// Formats accepted by `?archive=`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub fn from_query(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "zip" => Some(ArchiveFormat::Zip),
+            "tar.gz" | "tgz" => Some(ArchiveFormat::TarGz),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "application/zip",
+            ArchiveFormat::TarGz => "application/gzip",
+        }
+    }
+}
+
+/// A file or directory to add, relative to the archive root.
+#[derive(Debug)]
+struct Item {
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+}
+
+/// Stream `dir` as an archive whose entries sit under a top-level folder
+/// named `name`.
+///
+/// The archive is written on a blocking thread into a bounded pipe, so memory
+/// use does not grow with the size of the directory. Entries follow the
+/// listing rules: dotfiles only with `show_hidden`, symbolic links only with
+/// `follow_symlinks`.
+pub(super) fn archive_response(
+    dir: PathBuf,
+    name: String,
+    format: ArchiveFormat,
+    show_hidden: bool,
+    follow_symlinks: bool,
+) -> Response<Body> {
+    info!("Archiving {} as {}", dir.display(), format.extension());
+    let (reader, writer) = tokio::io::duplex(PIPE_SIZE);
+    let root = name.clone();
+    tokio::task::spawn_blocking(move || {
+        let writer = SyncIoBridge::new(writer);
+        let walker = Walker {
+            show_hidden,
+            follow_symlinks,
+            visited: HashSet::new(),
+        };
+        let result = match format {
+            ArchiveFormat::Zip => write_zip(writer, walker, &dir, &root),
+            ArchiveFormat::TarGz => write_tar_gz(writer, walker, &dir, &root),
+        };
+        // a closed pipe means the client went away
+        if let Err(e) = result {
+            warn!("Archive of {} aborted: {}", dir.display(), e);
+        }
+    });
+
+    let filename = format!("{}.{}", name, format.extension());
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            content_disposition(&filename, true),
+        )
+        .body(Body::from_stream(ReaderStream::new(reader)))
+        .unwrap()
+}
+
+struct Walker {
+    show_hidden: bool,
+    follow_symlinks: bool,
+    // canonical directories already archived, followed links may loop
+    visited: HashSet<PathBuf>,
+}
+
+impl Walker {
+    /// Call `add` for every entry below `dir`, parents before children.
+    fn walk(
+        &mut self,
+        dir: &Path,
+        prefix: &str,
+        add: &mut dyn FnMut(Item) -> io::Result<()>,
+    ) -> io::Result<()> {
+        if !self.visited.insert(dir.canonicalize()?) {
+            warn!("Skipping {}: already archived", dir.display());
+            return Ok(());
+        }
+        let options = ListingOptions {
+            sort: SortKey::Name,
+            desc: false,
+            show_hidden: self.show_hidden,
+        };
+        for entry in read_entries(dir, options, self.follow_symlinks)? {
+            let path = dir.join(&entry.name);
+            let name = format!("{}/{}", prefix, entry.name);
+            add(Item {
+                path: path.clone(),
+                name: name.clone(),
+                is_dir: entry.is_dir,
+            })?;
+            if entry.is_dir {
+                self.walk(&path, &name, add)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_zip(writer: impl Write, mut walker: Walker, dir: &Path, root: &str) -> io::Result<()> {
+    let mut zip = ZipWriter::new_stream(writer);
+    zip.add_directory(root, SimpleFileOptions::default())?;
+    walker.walk(dir, root, &mut |item| {
+        let mut options = SimpleFileOptions::default();
+        if let Some(time) = modified(&item.path) {
+            options = options.last_modified_time(time);
+        }
+        if item.is_dir {
+            zip.add_directory(item.name, options)?;
+            return Ok(());
+        }
+        // unreadable files are left out rather than breaking the archive
+        let mut file = match File::open(&item.path) {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("Skipping {}: {}", item.path.display(), e);
+                return Ok(());
+            }
+        };
+        let large = file.metadata()?.len() >= u32::MAX as u64;
+        zip.start_file(item.name, options.large_file(large))?;
+        io::copy(&mut file, &mut zip)?;
+        Ok(())
+    })?;
+    zip.finish()?.flush()
+}
+
+fn write_tar_gz(writer: impl Write, mut walker: Walker, dir: &Path, root: &str) -> io::Result<()> {
+    let mut tar = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+    tar.append_dir(root, dir)?;
+    walker.walk(dir, root, &mut |item| {
+        if item.is_dir {
+            return tar.append_dir(&item.name, &item.path);
+        }
+        let mut file = match File::open(&item.path) {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("Skipping {}: {}", item.path.display(), e);
+                return Ok(());
+            }
+        };
+        tar.append_file(&item.name, &mut file)
+    })?;
+    tar.into_inner()?.finish()?.flush()
+}
+
+/// The modification time as a zip timestamp, in local time like other tools.
+fn modified(path: &Path) -> Option<zip::DateTime> {
+    let time: DateTime<Local> = std::fs::metadata(path).ok()?.modified().ok()?.into();
+    zip::DateTime::from_date_and_time(
+        time.year().try_into().ok()?,
+        time.month() as u8,
+        time.day() as u8,
+        time.hour() as u8,
+        time.minute() as u8,
+        time.second() as u8,
+    )
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn fixture() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "a").unwrap();
+        std::fs::write(dir.path().join(".secret"), "s").unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("sub/b.txt"), "bb").unwrap();
+        dir
+    }
+
+    fn walker(show_hidden: bool) -> Walker {
+        Walker {
+            show_hidden,
+            follow_symlinks: false,
+            visited: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn test_archive_format() {
+        assert_eq!(ArchiveFormat::from_query("zip"), Some(ArchiveFormat::Zip));
+        assert_eq!(
+            ArchiveFormat::from_query("TAR.GZ"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(ArchiveFormat::from_query("tgz"), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_query("rar"), None);
+    }
+
+    #[test]
+    fn test_write_zip() {
+        let dir = fixture();
+        let mut out = Vec::new();
+        write_zip(&mut out, walker(false), dir.path(), "site").unwrap();
+
+        let mut zip = zip::ZipArchive::new(io::Cursor::new(out)).unwrap();
+        let mut names: Vec<_> = zip.file_names().map(str::to_string).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["site/", "site/a.txt", "site/sub/", "site/sub/b.txt"]
+        );
+        let mut content = String::new();
+        zip.by_name("site/sub/b.txt")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "bb");
+    }
+
+    #[test]
+    fn test_write_tar_gz() {
+        let dir = fixture();
+        let mut out = Vec::new();
+        write_tar_gz(&mut out, walker(true), dir.path(), "site").unwrap();
+
+        let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(&out[..]));
+        let mut files = Vec::new();
+        for entry in tar.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().display().to_string();
+            let mut content = String::new();
+            entry.read_to_string(&mut content).unwrap();
+            files.push((name, content));
+        }
+        let names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+        // directories come first, like in the listing
+        assert_eq!(
+            names,
+            [
+                "site",
+                "site/sub",
+                "site/sub/b.txt",
+                "site/.secret",
+                "site/a.txt"
+            ]
+        );
+        assert_eq!(files[2].1, "bb");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walk_symlinks() {
+        let dir = fixture();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("sub/loop")).unwrap();
+        let mut names = Vec::new();
+        let mut collect = |item: Item| {
+            names.push(item.name);
+            Ok(())
+        };
+        walker(false)
+            .walk(dir.path(), "site", &mut collect)
+            .unwrap();
+        assert!(!names.iter().any(|n| n.contains("loop")));
+
+        let mut names = Vec::new();
+        let mut collect = |item: Item| {
+            names.push(item.name);
+            Ok(())
+        };
+        let mut following = Walker {
+            follow_symlinks: true,
+            ..walker(false)
+        };
+        following.walk(dir.path(), "site", &mut collect).unwrap();
+        // the link is listed once, but not descended into again
+        assert!(names.contains(&"site/sub/loop".to_string()));
+        assert!(!names.iter().any(|n| n.starts_with("site/sub/loop/")));
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, Response, StatusCode};
+use axum::middleware::Next;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use log::warn;
+
+use crate::{Credential, HttpServeOpts};
+
+// remembered successful hash checks, so browsers resending credentials do
+// not pay for bcrypt or argon2 on every request
+const MAX_VERIFIED: usize = 1024;
+
+/// HTTP basic auth for `--auth` and `--auth-file`.
+pub(super) struct BasicAuth {
+    realm: String,
+    plain: Vec<Credential>,
+    // user to bcrypt or argon2 hash
+    hashed: HashMap<String, String>,
+    // SHA-256 of `user:password` pairs that matched a hash
+    verified: Mutex<HashSet<Vec<u8>>>,
+}
+
+/// The authenticated user, attached to responses for the access log.
+#[derive(Debug, Clone)]
+pub(super) struct AuthUser(pub String);
+
+impl BasicAuth {
+    /// Build the authenticator, or `None` when no credentials are configured.
+    pub fn new(opts: &HttpServeOpts) -> Result<Option<Self>> {
+        let hashed = match &opts.auth_file {
+            Some(path) => load_htpasswd(path)?,
+            None => HashMap::new(),
+        };
+        if opts.auth.is_empty() && hashed.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            realm: opts.auth_realm.replace(['"', '\\'], ""),
+            plain: opts.auth.clone(),
+            hashed,
+            verified: Mutex::new(HashSet::new()),
+        }))
+    }
+
+    async fn check(&self, user: &str, password: &str) -> bool {
+        let plain_match = self.plain.iter().any(|c| {
+            // evaluate both halves so timing does not reveal a valid user
+            let user_ok = constant_time_eq(c.user.as_bytes(), user.as_bytes());
+            let password_ok = constant_time_eq(c.password.as_bytes(), password.as_bytes());
+            user_ok & password_ok
+        });
+        if plain_match {
+            return true;
+        }
+        let Some(hash) = self.hashed.get(user).cloned() else {
+            return false;
+        };
+
+        let digest = ring::digest::digest(
+            &ring::digest::SHA256,
+            format!("{}:{}", user, password).as_bytes(),
+        )
+        .as_ref()
+        .to_vec();
+        if self.verified().contains(&digest) {
+            return true;
+        }
+        let password = password.to_string();
+        let ok = tokio::task::spawn_blocking(move || verify_hash(&password, &hash))
+            .await
+            .unwrap_or(false);
+        if ok {
+            let mut verified = self.verified();
+            if verified.len() >= MAX_VERIFIED {
+                verified.clear();
+            }
+            verified.insert(digest);
+        }
+        ok
+    }
+
+    fn verified(&self) -> std::sync::MutexGuard<'_, HashSet<Vec<u8>>> {
+        self.verified.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn challenge(&self) -> Response<Body> {
+        Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header(
+                header::WWW_AUTHENTICATE,
+                format!("Basic realm=\"{}\", charset=\"UTF-8\"", self.realm),
+            )
+            .body(Body::from("Unauthorized"))
+            .unwrap()
+    }
+}
+
+impl fmt::Debug for BasicAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BasicAuth")
+            .field("realm", &self.realm)
+            .field("plain", &self.plain)
+            .field("hashed_users", &self.hashed.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Middleware rejecting requests without valid basic auth credentials.
+pub(super) async fn auth_middleware(
+    State(auth): State<Arc<BasicAuth>>,
+    mut req: Request,
+    next: Next,
+) -> Response<Body> {
+    let Some((user, password)) = basic_credentials(req.headers()) else {
+        return auth.challenge();
+    };
+    if !auth.check(&user, &password).await {
+        warn!("Rejected credentials for user {}", user);
+        return auth.challenge();
+    }
+    // the credentials are rcli's own, `--proxy` upstreams must not see them
+    req.headers_mut().remove(header::AUTHORIZATION);
+    let mut response = next.run(req).await;
+    response.extensions_mut().insert(AuthUser(user));
+    response
+}
+
+/// Decode `Authorization: Basic base64(user:password)`.
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, encoded) = value.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = STANDARD.decode(encoded.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+/// Read `user:hash` lines, skipping blanks and `#` comments.
+fn load_htpasswd(path: &str) -> Result<HashMap<String, String>> {
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+    let mut users = HashMap::new();
+    for (n, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (user, hash) = line
+            .split_once(':')
+            .ok_or_else(|| anyhow!("{}:{}: expected user:hash", path, n + 1))?;
+        if !is_supported_hash(hash) {
+            return Err(anyhow!(
+                "{}:{}: unsupported hash for {}, use bcrypt (htpasswd -B) or argon2",
+                path,
+                n + 1,
+                user
+            ));
+        }
+        users.insert(user.to_string(), hash.to_string());
+    }
+    Ok(users)
+}
+
+fn is_supported_hash(hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$", "$argon2"]
+        .iter()
+        .any(|prefix| hash.starts_with(prefix))
+}
+
+fn verify_hash(password: &str, hash: &str) -> bool {
+    if hash.starts_with("$argon2") {
+        PasswordHash::new(hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    } else {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let diff = a
+        .iter()
+        .zip(b)
+        .fold(a.len() ^ b.len(), |acc, (x, y)| acc | (x ^ y) as usize);
+    diff == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::password_hash::SaltString;
+    use argon2::PasswordHasher;
+
+    use super::*;
+
+    #[test]
+    fn test_basic_credentials() {
+        let mut headers = HeaderMap::new();
+        let encoded = STANDARD.encode("admin:pa:ss");
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("basic {}", encoded).parse().unwrap(),
+        );
+        assert_eq!(
+            basic_credentials(&headers),
+            Some(("admin".to_string(), "pa:ss".to_string()))
+        );
+        headers.insert(header::AUTHORIZATION, "Bearer abc".parse().unwrap());
+        assert_eq!(basic_credentials(&headers), None);
+    }
+
+    #[test]
+    fn test_verify_hash() {
+        let bcrypt_hash = bcrypt::hash("secret", 4).unwrap();
+        assert!(verify_hash("secret", &bcrypt_hash));
+        assert!(!verify_hash("wrong", &bcrypt_hash));
+        // htpasswd -B writes the $2y$ variant
+        assert!(verify_hash(
+            "secret",
+            &bcrypt_hash.replacen("$2b$", "$2y$", 1)
+        ));
+
+        let argon2_hash = Argon2::default()
+            .hash_password(b"secret", &SaltString::encode_b64(b"rcli salt").unwrap())
+            .unwrap()
+            .to_string();
+        assert!(verify_hash("secret", &argon2_hash));
+        assert!(!verify_hash("wrong", &argon2_hash));
+    }
+
+    #[test]
+    fn test_load_htpasswd() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("htpasswd");
+        let hash = bcrypt::hash("secret", 4).unwrap();
+        std::fs::write(&path, format!("# users\n\nalice:{}\n", hash)).unwrap();
+        let users = load_htpasswd(path.to_str().unwrap()).unwrap();
+        assert_eq!(users.get("alice"), Some(&hash));
+
+        std::fs::write(&path, "bob:{SHA}fEqNCco3Yq9h5ZUglD3CZJT4lBs=\n").unwrap();
+        assert!(load_htpasswd(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+    }
+}
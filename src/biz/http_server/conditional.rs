@@ -0,0 +1,180 @@
+use std::fs::Metadata;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::http::{header, HeaderMap, HeaderName};
+
+/// Cache validators of a served file.
+#[derive(Debug, Clone)]
+pub(super) struct Validators {
+    /// Strong entity tag derived from modification time and size.
+    pub etag: String,
+    /// Modification time truncated to whole seconds, as HTTP dates are.
+    pub last_modified: SystemTime,
+}
+
+/// What to do with a request after evaluating its preconditions.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Precondition {
+    Proceed,
+    NotModified,
+    Failed,
+}
+
+impl Validators {
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        Self {
+            etag: format!("\"{:x}-{:x}\"", modified.as_nanos(), metadata.len()),
+            last_modified: UNIX_EPOCH + Duration::from_secs(modified.as_secs()),
+        }
+    }
+
+    pub fn last_modified_header(&self) -> String {
+        httpdate::fmt_http_date(self.last_modified)
+    }
+}
+
+/// Evaluate `If-Match`, `If-Unmodified-Since`, `If-None-Match` and
+/// `If-Modified-Since` in the order given by RFC 9110 §13.2.2.
+pub(super) fn evaluate(headers: &HeaderMap, validators: &Validators) -> Precondition {
+    if let Some(if_match) = header_str(headers, header::IF_MATCH) {
+        if !etag_list_matches(if_match, &validators.etag, true) {
+            return Precondition::Failed;
+        }
+    } else if let Some(since) = header_date(headers, header::IF_UNMODIFIED_SINCE) {
+        if validators.last_modified > since {
+            return Precondition::Failed;
+        }
+    }
+
+    if let Some(if_none_match) = header_str(headers, header::IF_NONE_MATCH) {
+        if etag_list_matches(if_none_match, &validators.etag, false) {
+            return Precondition::NotModified;
+        }
+    } else if let Some(since) = header_date(headers, header::IF_MODIFIED_SINCE) {
+        if validators.last_modified <= since {
+            return Precondition::NotModified;
+        }
+    }
+    Precondition::Proceed
+}
+
+/// Whether a `Range` header may be honoured given the request's `If-Range`.
+pub(super) fn if_range_matches(headers: &HeaderMap, validators: &Validators) -> bool {
+    let Some(if_range) = header_str(headers, header::IF_RANGE) else {
+        return true;
+    };
+    let if_range = if_range.trim();
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        strong_eq(if_range, &validators.etag)
+    } else {
+        httpdate::parse_http_date(if_range)
+            .map(|date| date == validators.last_modified)
+            .unwrap_or(false)
+    }
+}
+
+fn etag_list_matches(list: &str, etag: &str, strong: bool) -> bool {
+    list.split(',').map(str::trim).any(|candidate| {
+        candidate == "*"
+            || if strong {
+                strong_eq(candidate, etag)
+            } else {
+                weak_eq(candidate, etag)
+            }
+    })
+}
+
+fn strong_eq(a: &str, b: &str) -> bool {
+    !a.starts_with("W/") && !b.starts_with("W/") && a == b
+}
+
+fn weak_eq(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+fn header_str(headers: &HeaderMap, name: HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn header_date(headers: &HeaderMap, name: HeaderName) -> Option<SystemTime> {
+    header_str(headers, name).and_then(|v| httpdate::parse_http_date(v).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn validators() -> Validators {
+        Validators {
+            etag: "\"abc-10\"".to_string(),
+            last_modified: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        }
+    }
+
+    fn headers(pairs: &[(HeaderName, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.clone(), HeaderValue::from_str(v).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let v = validators();
+        let h = headers(&[(header::IF_NONE_MATCH, "\"xyz\", W/\"abc-10\"")]);
+        assert_eq!(evaluate(&h, &v), Precondition::NotModified);
+        let h = headers(&[(header::IF_NONE_MATCH, "\"xyz\"")]);
+        assert_eq!(evaluate(&h, &v), Precondition::Proceed);
+        let h = headers(&[(header::IF_NONE_MATCH, "*")]);
+        assert_eq!(evaluate(&h, &v), Precondition::NotModified);
+    }
+
+    #[test]
+    fn test_if_modified_since() {
+        let v = validators();
+        let h = headers(&[(header::IF_MODIFIED_SINCE, &v.last_modified_header())]);
+        assert_eq!(evaluate(&h, &v), Precondition::NotModified);
+        let earlier = httpdate::fmt_http_date(v.last_modified - Duration::from_secs(1));
+        let h = headers(&[(header::IF_MODIFIED_SINCE, &earlier)]);
+        assert_eq!(evaluate(&h, &v), Precondition::Proceed);
+        // If-None-Match takes precedence over If-Modified-Since
+        let h = headers(&[
+            (header::IF_NONE_MATCH, "\"xyz\""),
+            (header::IF_MODIFIED_SINCE, &v.last_modified_header()),
+        ]);
+        assert_eq!(evaluate(&h, &v), Precondition::Proceed);
+    }
+
+    #[test]
+    fn test_if_match() {
+        let v = validators();
+        let h = headers(&[(header::IF_MATCH, "\"abc-10\"")]);
+        assert_eq!(evaluate(&h, &v), Precondition::Proceed);
+        let h = headers(&[(header::IF_MATCH, "W/\"abc-10\"")]);
+        assert_eq!(evaluate(&h, &v), Precondition::Failed);
+        let earlier = httpdate::fmt_http_date(v.last_modified - Duration::from_secs(1));
+        let h = headers(&[(header::IF_UNMODIFIED_SINCE, &earlier)]);
+        assert_eq!(evaluate(&h, &v), Precondition::Failed);
+    }
+
+    #[test]
+    fn test_if_range() {
+        let v = validators();
+        assert!(if_range_matches(&HeaderMap::new(), &v));
+        let h = headers(&[(header::IF_RANGE, "\"abc-10\"")]);
+        assert!(if_range_matches(&h, &v));
+        let h = headers(&[(header::IF_RANGE, "\"stale\"")]);
+        assert!(!if_range_matches(&h, &v));
+        let h = headers(&[(header::IF_RANGE, &v.last_modified_header())]);
+        assert!(if_range_matches(&h, &v));
+        let h = headers(&[(header::IF_RANGE, "not a date")]);
+        assert!(!if_range_matches(&h, &v));
+    }
+}
@@ -0,0 +1,392 @@
+use std::io::{Cursor, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use axum::body::Body;
+use axum::http::{header, HeaderMap, Response, StatusCode};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use super::conditional::{evaluate, if_range_matches, Precondition, Validators};
+use super::range::{parse_range, RangeRequest};
+
+// number of leading bytes inspected when the extension gives no hint
+const SNIFF_LEN: usize = 512;
+
+const MAGIC_NUMBERS: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xFF\xD8\xFF", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1F\x8B", "application/gzip"),
+    (b"BZh", "application/x-bzip2"),
+    (b"\xFD7zXZ\x00", "application/x-xz"),
+    (b"\x28\xB5\x2F\xFD", "application/zstd"),
+    (b"\x7FELF", "application/x-executable"),
+    (b"\x00asm", "application/wasm"),
+];
+
+// encodings looked for by `--precompressed`, preferred first on equal quality
+const PRECOMPRESSED: &[(&str, &str)] = &[("br", "br"), ("zstd", "zst"), ("gzip", "gz")];
+
+/// A precompressed sibling of a served file, e.g. `app.js.br` for `app.js`.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct Variant {
+    pub path: PathBuf,
+    pub encoding: &'static str,
+}
+
+/// Pick the precompressed variant of `path` the client accepts best.
+///
+/// Only files whose type is known from the extension qualify, since the
+/// encoded bytes cannot be sniffed. Unless `follow_symlinks` is set, a
+/// variant that is a symbolic link is ignored like any other link.
+pub(super) fn precompressed_variant(
+    path: &Path,
+    headers: &HeaderMap,
+    follow_symlinks: bool,
+) -> Option<Variant> {
+    guess_content_type(path)?;
+    let accepted = accepted_encodings(headers);
+    let mut best: Option<(f32, Variant)> = None;
+    for (encoding, ext) in PRECOMPRESSED {
+        let q = accepted
+            .iter()
+            .find(|(name, _)| name == encoding)
+            .or_else(|| accepted.iter().find(|(name, _)| name == "*"))
+            .map_or(0.0, |(_, q)| *q);
+        if q <= 0.0 || best.as_ref().is_some_and(|(best_q, _)| *best_q >= q) {
+            continue;
+        }
+        let mut name = path.as_os_str().to_owned();
+        name.push(".");
+        name.push(ext);
+        let candidate = PathBuf::from(name);
+        let metadata = match follow_symlinks {
+            true => candidate.metadata(),
+            false => candidate.symlink_metadata(),
+        };
+        if metadata.is_ok_and(|metadata| metadata.is_file()) {
+            best = Some((
+                q,
+                Variant {
+                    path: candidate,
+                    encoding,
+                },
+            ));
+        }
+    }
+    best.map(|(_, variant)| variant)
+}
+
+/// `Accept-Encoding` as lower-cased names with their quality values.
+fn accepted_encodings(headers: &HeaderMap) -> Vec<(String, f32)> {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let name = parts.next().filter(|name| !name.is_empty())?;
+            let q = parts
+                .find_map(|p| p.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse().ok())?;
+            Some((name.to_lowercase(), q))
+        })
+        .collect()
+}
+
+/// Stream a regular file back to the client.
+///
+/// The body is never loaded into memory, so binary files are served
+/// byte-for-byte. Conditional requests and byte ranges are honoured, and
+/// `download` switches `Content-Disposition` to `attachment`. With a
+/// `variant` its bytes are sent as the encoded form of `path`.
+pub(super) async fn serve_file(
+    path: &Path,
+    variant: Option<&Variant>,
+    download: bool,
+    headers: &HeaderMap,
+) -> std::io::Result<Response<Body>> {
+    let source = variant.map_or(path, |v| v.path.as_path());
+    let mut file = File::open(source).await?;
+    let metadata = file.metadata().await?;
+    let len = metadata.len();
+    let validators = Validators::from_metadata(&metadata);
+    let mut builder = Response::builder()
+        .header(header::ETAG, &validators.etag)
+        .header(header::LAST_MODIFIED, validators.last_modified_header())
+        .header(header::ACCEPT_RANGES, "bytes");
+    if let Some(variant) = variant {
+        builder = builder
+            .header(header::CONTENT_ENCODING, variant.encoding)
+            .header(header::VARY, "accept-encoding");
+    }
+
+    match evaluate(headers, &validators) {
+        Precondition::Proceed => {}
+        Precondition::NotModified => {
+            return Ok(builder
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())
+                .unwrap())
+        }
+        Precondition::Failed => {
+            return Ok(builder
+                .status(StatusCode::PRECONDITION_FAILED)
+                .body(Body::empty())
+                .unwrap())
+        }
+    }
+
+    let ranges = match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(range) if if_range_matches(headers, &validators) => parse_range(range, len),
+        _ => RangeRequest::Full,
+    };
+    if ranges == RangeRequest::Unsatisfiable {
+        return Ok(builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    let content_type = match guess_content_type(path) {
+        Some(content_type) => content_type,
+        None => {
+            let mut head = [0u8; SNIFF_LEN];
+            let n = read_head(&mut file, &mut head).await?;
+            file.seek(SeekFrom::Start(0)).await?;
+            sniff_content_type(&head[..n]).to_string()
+        }
+    };
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let builder = builder.header(
+        header::CONTENT_DISPOSITION,
+        content_disposition(&name, download),
+    );
+
+    let response = match ranges {
+        RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+            let (start, end) = ranges[0];
+            file.seek(SeekFrom::Start(start)).await?;
+            let section = file.take(end - start + 1);
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_TYPE, content_type)
+                .header(header::CONTENT_LENGTH, end - start + 1)
+                .header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, end, len),
+                )
+                .body(Body::from_stream(ReaderStream::new(section)))
+                .unwrap()
+        }
+        RangeRequest::Partial(ranges) => {
+            let boundary = format!("{:016x}", rand::random::<u64>());
+            let mut body: Box<dyn AsyncRead + Send + Unpin> = Box::new(tokio::io::empty());
+            let mut body_len = 0;
+            for (i, (start, end)) in ranges.into_iter().enumerate() {
+                let part_header = format!(
+                    "{}--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                    if i == 0 { "" } else { "\r\n" },
+                    boundary,
+                    content_type,
+                    start,
+                    end,
+                    len
+                );
+                let mut section = File::open(source).await?;
+                section.seek(SeekFrom::Start(start)).await?;
+                body_len += part_header.len() as u64 + end - start + 1;
+                body = Box::new(
+                    body.chain(Cursor::new(part_header))
+                        .chain(section.take(end - start + 1)),
+                );
+            }
+            let trailer = format!("\r\n--{}--\r\n", boundary);
+            body_len += trailer.len() as u64;
+            let body = body.chain(Cursor::new(trailer));
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(
+                    header::CONTENT_TYPE,
+                    format!("multipart/byteranges; boundary={}", boundary),
+                )
+                .header(header::CONTENT_LENGTH, body_len)
+                .body(Body::from_stream(ReaderStream::new(body)))
+                .unwrap()
+        }
+        _ => builder
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, len)
+            .body(Body::from_stream(ReaderStream::new(file)))
+            .unwrap(),
+    };
+    Ok(response)
+}
+
+/// MIME type derived from the file extension, with a utf-8 charset for text.
+pub(super) fn guess_content_type(path: &Path) -> Option<String> {
+    let mime = mime_guess::from_path(path).first()?;
+    let textual = mime.type_() == mime_guess::mime::TEXT
+        || matches!(
+            mime.subtype().as_str(),
+            "json" | "javascript" | "xml" | "toml" | "yaml"
+        );
+    if textual && mime.get_param(mime_guess::mime::CHARSET).is_none() {
+        Some(format!("{}; charset=utf-8", mime.essence_str()))
+    } else {
+        Some(mime.to_string())
+    }
+}
+
+/// MIME type guessed from the first bytes of a file.
+pub(super) fn sniff_content_type(head: &[u8]) -> &'static str {
+    for (magic, mime) in MAGIC_NUMBERS {
+        if head.starts_with(magic) {
+            return mime;
+        }
+    }
+    if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+    if head.len() >= 262 && &head[257..262] == b"ustar" {
+        return "application/x-tar";
+    }
+    if head.contains(&0) {
+        return "application/octet-stream";
+    }
+    // a multibyte character may be cut off at the end of the sniffed window
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).unwrap(),
+        Err(_) => return "application/octet-stream",
+    };
+    let start = text.trim_start().to_ascii_lowercase();
+    if start.starts_with("<!doctype html") || start.starts_with("<html") {
+        "text/html; charset=utf-8"
+    } else {
+        "text/plain; charset=utf-8"
+    }
+}
+
+/// `Content-Disposition` value, with an RFC 5987 `filename*` for non-ASCII names.
+pub(super) fn content_disposition(name: &str, download: bool) -> String {
+    let kind = if download { "attachment" } else { "inline" };
+    if name.is_empty() {
+        return kind.to_string();
+    }
+    let plain = name
+        .chars()
+        .all(|c| c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\');
+    if plain {
+        format!("{}; filename=\"{}\"", kind, name)
+    } else {
+        let fallback: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!(
+            "{}; filename=\"{}\"; filename*=UTF-8''{}",
+            kind,
+            fallback,
+            utf8_percent_encode(name, NON_ALPHANUMERIC)
+        )
+    }
+}
+
+async fn read_head(file: &mut File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = file.read(&mut buf[filled..]).await?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accepted_encodings() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            "gzip, BR;q=0.8, zstd;q=0, *;q=0.1".parse().unwrap(),
+        );
+        assert_eq!(
+            accepted_encodings(&headers),
+            vec![
+                ("gzip".to_string(), 1.0),
+                ("br".to_string(), 0.8),
+                ("zstd".to_string(), 0.0),
+                ("*".to_string(), 0.1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_guess_content_type() {
+        let ct = |p: &str| guess_content_type(Path::new(p));
+        assert_eq!(ct("a.json").unwrap(), "application/json; charset=utf-8");
+        assert_eq!(ct("index.html").unwrap(), "text/html; charset=utf-8");
+        assert_eq!(ct("logo.png").unwrap(), "image/png");
+        assert_eq!(ct("doc.pdf").unwrap(), "application/pdf");
+        assert!(ct("Makefile").is_none());
+    }
+
+    #[test]
+    fn test_sniff_content_type() {
+        assert_eq!(sniff_content_type(b"\x89PNG\r\n\x1a\n\0\0"), "image/png");
+        assert_eq!(sniff_content_type(b"%PDF-1.7"), "application/pdf");
+        assert_eq!(
+            sniff_content_type(b"  <!DOCTYPE html><html>"),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(sniff_content_type(b"hello"), "text/plain; charset=utf-8");
+        // truncated multibyte character at the end of the window
+        assert_eq!(
+            sniff_content_type(&"中文".as_bytes()[..4]),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(
+            sniff_content_type(b"\x01\x02\x00\x03"),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition("a.txt", false),
+            "inline; filename=\"a.txt\""
+        );
+        assert_eq!(
+            content_disposition("a.txt", true),
+            "attachment; filename=\"a.txt\""
+        );
+        assert_eq!(
+            content_disposition("报告.pdf", true),
+            "attachment; filename=\"__.pdf\"; filename*=UTF-8''%E6%8A%A5%E5%91%8A%2Epdf"
+        );
+    }
+}
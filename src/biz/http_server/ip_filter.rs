@@ -0,0 +1,91 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{Response, StatusCode};
+use axum::middleware::Next;
+use log::warn;
+
+use crate::{Cidr, HttpServeOpts};
+
+/// `--allow` and `--deny` address ranges.
+#[derive(Debug)]
+pub(super) struct IpFilter {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+impl IpFilter {
+    /// Build the filter, or `None` when neither list is given.
+    pub fn new(opts: &HttpServeOpts) -> Option<Self> {
+        if opts.allow.is_empty() && opts.deny.is_empty() {
+            return None;
+        }
+        Some(Self {
+            allow: opts.allow.clone(),
+            deny: opts.deny.clone(),
+        })
+    }
+
+    /// Deny wins over allow; with an allow list only listed clients pass.
+    ///
+    /// Clients without an address, i.e. on a unix socket, are already
+    /// vetted by the socket's file permissions and always pass.
+    fn permits(&self, ip: Option<IpAddr>) -> bool {
+        let Some(ip) = ip else {
+            return true;
+        };
+        if self.deny.iter().any(|cidr| cidr.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(ip))
+    }
+}
+
+/// Middleware answering `403 Forbidden` to clients the filter rejects.
+pub(super) async fn ip_filter_middleware(
+    State(filter): State<Arc<IpFilter>>,
+    req: Request,
+    next: Next,
+) -> Response<Body> {
+    let ip = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    if filter.permits(ip) {
+        return next.run(req).await;
+    }
+    warn!("Rejected client {:?}", ip);
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .body(Body::from("Forbidden"))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(allow: &[&str], deny: &[&str]) -> IpFilter {
+        IpFilter {
+            allow: allow.iter().map(|c| c.parse().unwrap()).collect(),
+            deny: deny.iter().map(|c| c.parse().unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_permits() {
+        let ip = |s: &str| Some(s.parse::<IpAddr>().unwrap());
+        let private = filter(&["10.0.0.0/8", "::1"], &["10.0.0.13"]);
+        assert!(private.permits(ip("10.2.3.4")));
+        assert!(private.permits(ip("::1")));
+        assert!(!private.permits(ip("10.0.0.13")));
+        assert!(!private.permits(ip("192.168.1.1")));
+        assert!(private.permits(None));
+
+        let blocklist = filter(&[], &["192.168.0.0/16"]);
+        assert!(blocklist.permits(ip("10.2.3.4")));
+        assert!(!blocklist.permits(ip("192.168.1.1")));
+    }
+}
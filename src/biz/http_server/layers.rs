@@ -0,0 +1,160 @@
+use anyhow::{anyhow, Result};
+use axum::body::HttpBody;
+use axum::http::{header, HeaderName, HeaderValue, Response};
+use tower_http::compression::predicate::{DefaultPredicate, Predicate};
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
+
+use crate::HttpServeOpts;
+
+// content types that are already compressed, or ranges that must be served verbatim
+const INCOMPRESSIBLE: &[&str] = &[
+    "multipart/byteranges",
+    "application/zip",
+    "application/gzip",
+    "application/x-gzip",
+    "application/zstd",
+    "application/x-bzip2",
+    "application/x-xz",
+    "application/x-7z-compressed",
+    "application/vnd.rar",
+    "application/x-rar-compressed",
+    "audio/",
+    "video/",
+    "font/woff",
+];
+
+/// Compress responses worth compressing: tower-http's defaults (no images,
+/// no tiny bodies) minus archives, media and multipart ranges.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct Compressible;
+
+impl Predicate for Compressible {
+    fn should_compress<B>(&self, response: &Response<B>) -> bool
+    where
+        B: HttpBody,
+    {
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        DefaultPredicate::new().should_compress(response)
+            && !INCOMPRESSIBLE.iter().any(|t| content_type.starts_with(t))
+    }
+}
+
+/// A compressed body is a different representation, so its entity tag can
+/// no longer be strong.
+pub(super) async fn weaken_etag<B>(mut response: Response<B>) -> Response<B> {
+    if !response.headers().contains_key(header::CONTENT_ENCODING) {
+        return response;
+    }
+    let weak = response
+        .headers()
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .and_then(|etag| HeaderValue::from_str(&format!("W/{}", etag)).ok());
+    if let Some(weak) = weak {
+        response.headers_mut().insert(header::ETAG, weak);
+    }
+    response
+}
+
+/// Build the `--cors` layer, or `None` when CORS is off.
+pub(super) fn cors_layer(opts: &HttpServeOpts) -> Result<Option<CorsLayer>> {
+    if !opts.cors {
+        return Ok(None);
+    }
+    let origins = if opts.cors_origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = opts
+            .cors_origins
+            .iter()
+            .map(|o| HeaderValue::from_str(o).map_err(|_| anyhow!("Invalid CORS origin {}", o)))
+            .collect::<Result<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+    let headers = if opts.cors_headers.iter().any(|h| h == "*") {
+        AllowHeaders::any()
+    } else {
+        let headers = opts
+            .cors_headers
+            .iter()
+            .map(|h| {
+                HeaderName::from_bytes(h.as_bytes())
+                    .map_err(|_| anyhow!("Invalid CORS header {}", h))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        AllowHeaders::list(headers)
+    };
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(opts.cors_methods.clone())
+            .allow_headers(headers)
+            // let scripts read what range and cache aware clients need
+            .expose_headers([
+                header::ACCEPT_RANGES,
+                header::CONTENT_DISPOSITION,
+                header::CONTENT_LENGTH,
+                header::CONTENT_RANGE,
+                header::ETAG,
+                header::LOCATION,
+            ]),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn opts(args: &[&str]) -> HttpServeOpts {
+        HttpServeOpts::try_parse_from(["serve"].iter().chain(args)).unwrap()
+    }
+
+    #[test]
+    fn test_cors_layer() {
+        assert!(cors_layer(&opts(&[])).unwrap().is_none());
+        assert!(cors_layer(&opts(&["--cors"])).unwrap().is_some());
+        assert!(cors_layer(&opts(&[
+            "--cors",
+            "--cors-origins",
+            "https://a.example,https://b.example",
+            "--cors-headers",
+            "x-token"
+        ]))
+        .unwrap()
+        .is_some());
+        assert!(cors_layer(&opts(&["--cors", "--cors-origins", "bad\norigin"])).is_err());
+        assert!(cors_layer(&opts(&["--cors", "--cors-headers", "bad header"])).is_err());
+        assert!(
+            HttpServeOpts::try_parse_from(["serve", "--cors-methods", "GET,NOT A METHOD"]).is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_weaken_etag() {
+        let response = Response::builder()
+            .header(header::ETAG, "\"abc\"")
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            weaken_etag(response).await.headers()[header::ETAG],
+            "W/\"abc\""
+        );
+
+        let response = Response::builder()
+            .header(header::ETAG, "\"abc\"")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            weaken_etag(response).await.headers()[header::ETAG],
+            "\"abc\""
+        );
+    }
+}
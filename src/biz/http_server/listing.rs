@@ -0,0 +1,428 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use axum::body::Body;
+use axum::http::{header, HeaderMap, Response, StatusCode};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::Serialize;
+
+use super::HttpServerState;
+use crate::utils::html_escape;
+
+// characters escaped when a file name is turned into a link
+pub(super) const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'&')
+    .add(b'+')
+    .add(b'/')
+    .add(b':')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+/// How a directory listing is sorted and filtered, taken from the query string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct ListingOptions {
+    pub sort: SortKey,
+    pub desc: bool,
+    pub show_hidden: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct Entry {
+    pub name: String,
+    pub href: String,
+    pub is_dir: bool,
+    pub size: u64,
+    /// Seconds since the unix epoch.
+    pub modified: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct Listing<'a> {
+    path: &'a str,
+    entries: &'a [Entry],
+}
+
+impl ListingOptions {
+    pub fn from_query(query: &HashMap<String, String>) -> Self {
+        let sort = match query.get("sort").map(String::as_str) {
+            Some("size") => SortKey::Size,
+            Some("modified") | Some("date") => SortKey::Modified,
+            _ => SortKey::Name,
+        };
+        let flag = |key: &str| {
+            query
+                .get(key)
+                .is_some_and(|v| matches!(v.as_str(), "" | "1" | "true" | "yes"))
+        };
+        Self {
+            sort,
+            desc: query.get("order").is_some_and(|v| v == "desc"),
+            show_hidden: flag("hidden"),
+        }
+    }
+
+    fn query_string(&self, sort: SortKey, desc: bool, show_hidden: bool) -> String {
+        let sort = match sort {
+            SortKey::Name => "name",
+            SortKey::Size => "size",
+            SortKey::Modified => "modified",
+        };
+        let order = if desc { "desc" } else { "asc" };
+        let mut query = format!("?sort={}&order={}", sort, order);
+        if show_hidden {
+            query.push_str("&hidden=true");
+        }
+        query
+    }
+}
+
+/// Whether a directory entry is a dotfile, hidden from listings by default.
+pub(super) fn is_hidden(name: &str) -> bool {
+    name.starts_with('.')
+}
+
+/// Read the entries of `dir`, sorted with directories first.
+///
+/// Symbolic links are left out unless `follow_symlinks` is set, since they
+/// could not be served anyway.
+pub(super) fn read_entries(
+    dir: &Path,
+    options: ListingOptions,
+    follow_symlinks: bool,
+) -> std::io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !options.show_hidden && is_hidden(&name) {
+            continue;
+        }
+        let metadata = if entry.file_type()?.is_symlink() {
+            if !follow_symlinks {
+                continue;
+            }
+            // dangling links are skipped
+            match std::fs::metadata(entry.path()) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            }
+        } else {
+            entry.metadata()?
+        };
+        let is_dir = metadata.is_dir();
+        let mut href = utf8_percent_encode(&name, PATH_SEGMENT).to_string();
+        if is_dir {
+            href.push('/');
+        }
+        entries.push(Entry {
+            name,
+            href,
+            is_dir,
+            size: if is_dir { 0 } else { metadata.len() },
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        });
+    }
+
+    entries.sort_by(|a, b| {
+        let ordering = match options.sort {
+            SortKey::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+        }
+        .then_with(|| a.name.cmp(&b.name));
+        let ordering = if options.desc {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+        b.is_dir.cmp(&a.is_dir).then(ordering)
+    });
+    Ok(entries)
+}
+
+/// Render the listing of `dir`, reached at the (still encoded) `url_path`.
+///
+/// Clients that ask for `application/json` get the entries as JSON instead of
+/// an HTML page.
+pub(super) fn listing_response(
+    dir: &Path,
+    url_path: &str,
+    query: &HashMap<String, String>,
+    headers: &HeaderMap,
+    state: &HttpServerState,
+) -> std::io::Result<Response<Body>> {
+    let options = ListingOptions::from_query(query);
+    let entries = read_entries(dir, options, state.opts.follow_symlinks)?;
+    let path = percent_decode_str(url_path).decode_utf8_lossy();
+
+    let wants_json = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("application/json"));
+    let (content_type, body) = if wants_json {
+        let listing = Listing {
+            path: &path,
+            entries: &entries,
+        };
+        (
+            "application/json",
+            serde_json::to_string(&listing).map_err(std::io::Error::other)?,
+        )
+    } else {
+        (
+            "text/html; charset=utf-8",
+            render_html(&path, &entries, options, state.opts.upload),
+        )
+    };
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::VARY, "Accept")
+        .body(Body::from(body))
+        .unwrap())
+}
+
+fn render_html(path: &str, entries: &[Entry], options: ListingOptions, upload: bool) -> String {
+    let title = html_escape(path);
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>Index of {title}</title>\n<style>\n\
+         body {{ font-family: sans-serif; margin: 2em; }}\n\
+         table {{ border-collapse: collapse; }}\n\
+         th, td {{ padding: 0.2em 1em; text-align: left; }}\n\
+         td.size {{ text-align: right; font-family: monospace; }}\n\
+         a {{ text-decoration: none; }}\n\
+         </style>\n</head>\n<body>\n<h1>Index of {title}</h1>\n"
+    );
+
+    let hidden_link = options.query_string(options.sort, options.desc, !options.show_hidden);
+    let _ = writeln!(
+        html,
+        "<p><a href=\"{}\">{}</a></p>",
+        html_escape(&hidden_link),
+        if options.show_hidden {
+            "Hide hidden files"
+        } else {
+            "Show hidden files"
+        }
+    );
+
+    let hidden = if options.show_hidden {
+        "&hidden=true"
+    } else {
+        ""
+    };
+    let _ = writeln!(
+        html,
+        "<p>Download as <a href=\"?archive=zip{hidden}\">zip</a> \
+         or <a href=\"?archive=tar.gz{hidden}\">tar.gz</a></p>",
+        hidden = html_escape(hidden)
+    );
+
+    if upload {
+        html.push_str(
+            "<form method=\"post\" enctype=\"multipart/form-data\">\
+             <input type=\"file\" name=\"file\" multiple required> \
+             <button type=\"submit\">Upload</button></form>\n",
+        );
+    }
+
+    html.push_str("<table>\n<thead><tr>");
+    for (label, key) in [
+        ("Name", SortKey::Name),
+        ("Size", SortKey::Size),
+        ("Modified", SortKey::Modified),
+    ] {
+        // clicking the active column flips the order
+        let desc = options.sort == key && !options.desc;
+        let arrow = match (options.sort == key, options.desc) {
+            (true, false) => " ▲",
+            (true, true) => " ▼",
+            _ => "",
+        };
+        let _ = write!(
+            html,
+            "<th><a href=\"{}\">{}{}</a></th>",
+            html_escape(&options.query_string(key, desc, options.show_hidden)),
+            label,
+            arrow
+        );
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+
+    if path != "/" {
+        html.push_str("<tr><td>⬆️ <a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for entry in entries {
+        let (size, name) = if entry.is_dir {
+            ("-".to_string(), format!("{}/", entry.name))
+        } else {
+            (human_size(entry.size), entry.name.clone())
+        };
+        let _ = writeln!(
+            html,
+            "<tr><td>{} <a href=\"{}\">{}</a></td><td class=\"size\">{}</td><td>{}</td></tr>",
+            icon(entry),
+            html_escape(&entry.href),
+            html_escape(&name),
+            size,
+            format_time(entry.modified)
+        );
+    }
+    html.push_str("</tbody>\n</table>\n</body>\n</html>\n");
+    html
+}
+
+fn icon(entry: &Entry) -> &'static str {
+    if entry.is_dir {
+        return "📁";
+    }
+    let Some(mime) = mime_guess::from_path(&entry.name).first() else {
+        return "📄";
+    };
+    match (mime.type_().as_str(), mime.subtype().as_str()) {
+        ("image", _) => "🖼️",
+        ("video", _) => "🎞️",
+        ("audio", _) => "🎵",
+        ("text", _) => "📝",
+        (
+            _,
+            "zip" | "gzip" | "x-tar" | "x-gzip" | "x-bzip2" | "x-xz" | "zstd" | "x-7z-compressed",
+        ) => "📦",
+        (_, "pdf") => "📕",
+        _ => "📄",
+    }
+}
+
+fn human_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Format unix seconds as `YYYY-MM-DD HH:MM` in UTC.
+fn format_time(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_listing_options() {
+        let options = ListingOptions::from_query(&query(&[("sort", "size"), ("order", "desc")]));
+        assert_eq!(options.sort, SortKey::Size);
+        assert!(options.desc);
+        assert!(!options.show_hidden);
+        let options = ListingOptions::from_query(&query(&[("hidden", "true")]));
+        assert_eq!(options.sort, SortKey::Name);
+        assert!(options.show_hidden);
+    }
+
+    #[test]
+    fn test_read_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("b.txt"), "bb").unwrap();
+        std::fs::write(dir.path().join("a b&c.txt"), "a").unwrap();
+        std::fs::write(dir.path().join(".hidden"), "").unwrap();
+        std::fs::write(dir.path().join("c.txt"), "ccc").unwrap();
+        std::fs::create_dir(dir.path().join("z")).unwrap();
+
+        let options = ListingOptions::from_query(&HashMap::new());
+        let entries = read_entries(dir.path(), options, false).unwrap();
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["z", "a b&c.txt", "b.txt", "c.txt"]);
+        assert_eq!(entries[0].href, "z/");
+        assert_eq!(entries[1].href, "a%20b%26c.txt");
+
+        let options = ListingOptions::from_query(&query(&[
+            ("sort", "size"),
+            ("order", "desc"),
+            ("hidden", "1"),
+        ]));
+        let entries = read_entries(dir.path(), options, false).unwrap();
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["z", "c.txt", "b.txt", "a b&c.txt", ".hidden"]);
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01 00:00");
+        assert_eq!(format_time(951_782_400), "2000-02-29 00:00");
+        assert_eq!(format_time(1_700_000_000), "2023-11-14 22:13");
+    }
+}
@@ -0,0 +1,240 @@
+use std::fmt::Write;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::{Request, State};
+use axum::http::{header, Response, StatusCode};
+use axum::routing::get;
+use axum::Router;
+use futures_util::future::BoxFuture;
+use hyper::body::{Frame, SizeHint};
+use tower::{Layer, Service};
+
+use super::serve::Activity;
+
+// upper bounds in seconds, the Prometheus client defaults
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Request counters behind `/metrics`.
+#[derive(Debug)]
+pub(super) struct Metrics {
+    activity: Arc<Activity>,
+    // indexed by status code
+    requests: Vec<AtomicU64>,
+    bytes_sent: AtomicU64,
+    // per bucket, the last one counts requests slower than every bound
+    latency: Vec<AtomicU64>,
+    latency_sum_micros: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new(activity: Arc<Activity>) -> Self {
+        Self {
+            activity,
+            requests: (0..1000).map(|_| AtomicU64::new(0)).collect(),
+            bytes_sent: AtomicU64::new(0),
+            latency: (0..=BUCKETS.len()).map(|_| AtomicU64::new(0)).collect(),
+            latency_sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, status: StatusCode, elapsed: Duration) {
+        self.requests[status.as_u16() as usize].fetch_add(1, Ordering::Relaxed);
+        let seconds = elapsed.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.latency[bucket].fetch_add(1, Ordering::Relaxed);
+        self.latency_sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// The Prometheus text exposition format.
+    fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str(
+            "# HELP rcli_http_requests_total Requests handled, by response status.\n\
+             # TYPE rcli_http_requests_total counter\n",
+        );
+        for (status, count) in self.requests.iter().enumerate() {
+            let count = count.load(Ordering::Relaxed);
+            if count > 0 {
+                let _ = writeln!(
+                    out,
+                    "rcli_http_requests_total{{status=\"{}\"}} {}",
+                    status, count
+                );
+            }
+        }
+
+        let _ = write!(
+            out,
+            "# HELP rcli_http_response_bytes_total Response body bytes sent.\n\
+             # TYPE rcli_http_response_bytes_total counter\n\
+             rcli_http_response_bytes_total {}\n",
+            self.bytes_sent.load(Ordering::Relaxed)
+        );
+
+        out.push_str(
+            "# HELP rcli_http_request_duration_seconds Time until the response head is ready.\n\
+             # TYPE rcli_http_request_duration_seconds histogram\n",
+        );
+        let mut cumulative = 0;
+        for (i, count) in self.latency.iter().enumerate() {
+            cumulative += count.load(Ordering::Relaxed);
+            let le = BUCKETS
+                .get(i)
+                .map_or_else(|| "+Inf".to_string(), |bound| bound.to_string());
+            let _ = writeln!(
+                out,
+                "rcli_http_request_duration_seconds_bucket{{le=\"{}\"}} {}",
+                le, cumulative
+            );
+        }
+        let sum = self.latency_sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = write!(
+            out,
+            "rcli_http_request_duration_seconds_sum {}\n\
+             rcli_http_request_duration_seconds_count {}\n",
+            sum, cumulative
+        );
+
+        let _ = write!(
+            out,
+            "# HELP rcli_http_active_connections Open client connections.\n\
+             # TYPE rcli_http_active_connections gauge\n\
+             rcli_http_active_connections {}\n",
+            self.activity.connections()
+        );
+        out
+    }
+}
+
+/// `/healthz` and `/metrics`.
+pub(super) fn admin_router(metrics: Arc<Metrics>) -> Router {
+    Router::new()
+        .route("/healthz", get(|| async { "ok\n" }))
+        .route("/metrics", get(metrics_handler))
+        .with_state(metrics)
+}
+
+async fn metrics_handler(State(metrics): State<Arc<Metrics>>) -> Response<Body> {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(metrics.render()))
+        .unwrap()
+}
+
+/// Counts every request passing through into [`Metrics`].
+#[derive(Debug, Clone)]
+pub(super) struct MetricsLayer(pub Arc<Metrics>);
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            metrics: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct MetricsService<S> {
+    inner: S,
+    metrics: Arc<Metrics>,
+}
+
+impl<S> Service<Request> for MetricsService<S>
+where
+    S: Service<Request, Response = Response<Body>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let started = Instant::now();
+        let metrics = self.metrics.clone();
+        let response = self.inner.call(req);
+        Box::pin(async move {
+            let response = response.await?;
+            metrics.observe(response.status(), started.elapsed());
+            // bytes are counted as they are sent, streamed bodies included
+            Ok(response.map(|inner| Body::new(CountingBody { inner, metrics })))
+        })
+    }
+}
+
+struct CountingBody {
+    inner: Body,
+    metrics: Arc<Metrics>,
+}
+
+impl HttpBody for CountingBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let this = self.get_mut();
+        let frame = Pin::new(&mut this.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &frame {
+            if let Some(data) = frame.data_ref() {
+                this.metrics
+                    .bytes_sent
+                    .fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new(Arc::new(Activity::default()));
+        metrics.observe(StatusCode::OK, Duration::from_millis(3));
+        metrics.observe(StatusCode::OK, Duration::from_millis(200));
+        metrics.observe(StatusCode::NOT_FOUND, Duration::from_secs(20));
+        metrics.bytes_sent.fetch_add(42, Ordering::Relaxed);
+
+        let text = metrics.render();
+        assert!(text.contains("rcli_http_requests_total{status=\"200\"} 2\n"));
+        assert!(text.contains("rcli_http_requests_total{status=\"404\"} 1\n"));
+        assert!(!text.contains("status=\"500\""));
+        assert!(text.contains("rcli_http_response_bytes_total 42\n"));
+        assert!(text.contains("rcli_http_request_duration_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(text.contains("rcli_http_request_duration_seconds_bucket{le=\"0.25\"} 2\n"));
+        assert!(text.contains("rcli_http_request_duration_seconds_bucket{le=\"10\"} 2\n"));
+        assert!(text.contains("rcli_http_request_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("rcli_http_request_duration_seconds_count 3\n"));
+        assert!(text.contains("rcli_http_active_connections 0\n"));
+    }
+}
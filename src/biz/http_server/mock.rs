@@ -0,0 +1,534 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use axum::body::{to_bytes, Body};
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderName, HeaderValue, Method, Response, StatusCode};
+use axum::Router;
+use chrono::Local;
+use log::{info, warn};
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
+use tracing::Level;
+
+use super::serve::{shutdown_signal, Activity, Listener, Server};
+use crate::HttpMockOpts;
+
+// request bodies are kept whole for the record
+const MAX_BODY: usize = 16 << 20;
+
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RouteFile {
+    routes: Vec<RouteSpec>,
+}
+
+/// A route as written in the route file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RouteSpec {
+    method: Option<String>,
+    path: String,
+    #[serde(default = "default_status")]
+    status: u16,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    body: Option<String>,
+    body_file: Option<String>,
+    delay: Option<String>,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    /// Matches the rest of the path, slashes included.
+    Rest(String),
+}
+
+/// A path pattern in axum syntax: `/users/:id` or `/static/*path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PathPattern(Vec<Segment>);
+
+#[derive(Debug)]
+enum MockBody {
+    /// Text with `{{param}}` placeholders.
+    Template(String),
+    Bytes(Vec<u8>),
+}
+
+#[derive(Debug)]
+struct MockRoute {
+    method: Option<Method>,
+    path: String,
+    pattern: PathPattern,
+    status: StatusCode,
+    headers: Vec<(HeaderName, String)>,
+    body: MockBody,
+    delay: Option<Duration>,
+}
+
+/// The loaded route file and the request recorder.
+pub(super) struct Mock {
+    routes: Vec<MockRoute>,
+    record: Option<Mutex<Box<dyn Write + Send>>>,
+}
+
+/// Serve the routes of `opts.routes` until interrupted.
+pub async fn http_mock(opts: HttpMockOpts) -> Result<()> {
+    let mock = Mock::load(Path::new(&opts.routes), opts.record.as_deref())?;
+    let mut listeners = Vec::new();
+    for bind in &opts.bind {
+        listeners.extend(Listener::bind(bind, opts.port).await?);
+    }
+    for listener in &listeners {
+        info!(
+            "Mocking {} route(s) from {} on http://{}",
+            mock.routes.len(),
+            opts.routes,
+            listener.local_addr()
+        );
+    }
+    let server = Server {
+        listeners,
+        router: mock_router(mock),
+        tls: None,
+        activity: Arc::new(Activity::default()),
+        shutdown_timeout: SHUTDOWN_TIMEOUT,
+    };
+    server.run(shutdown_signal()).await
+}
+
+pub(super) fn mock_router(mock: Mock) -> Router {
+    Router::new()
+        .fallback(mock_handler)
+        .with_state(Arc::new(mock))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
+                .on_response(DefaultOnResponse::new().level(Level::DEBUG)),
+        )
+}
+
+impl Mock {
+    /// Load the route file at `path`; `body_file`s are relative to it.
+    pub fn load(path: &Path, record: Option<&str>) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        // JSON is valid YAML, so one parser covers both
+        let file: RouteFile = serde_yaml::from_str(&content)
+            .with_context(|| format!("Invalid route file {}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        let routes = file
+            .routes
+            .into_iter()
+            .enumerate()
+            .map(|(i, spec)| {
+                MockRoute::compile(spec, base)
+                    .with_context(|| format!("Invalid route #{} in {}", i + 1, path.display()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let record = match record {
+            Some("-") => Some(Box::new(std::io::stdout()) as Box<dyn Write + Send>),
+            Some(record) => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(record)
+                    .with_context(|| format!("Failed to open request record {}", record))?;
+                Some(Box::new(LineWriter::new(file)) as Box<dyn Write + Send>)
+            }
+            None => None,
+        };
+        Ok(Self {
+            routes,
+            record: record.map(Mutex::new),
+        })
+    }
+
+    fn find(&self, method: &Method, path: &str) -> Option<(&MockRoute, HashMap<String, String>)> {
+        self.routes
+            .iter()
+            .filter(|route| route.method.is_none() || route.method.as_ref() == Some(method))
+            .find_map(|route| route.pattern.matches(path).map(|params| (route, params)))
+    }
+
+    fn record(&self, entry: serde_json::Value) {
+        let Some(record) = &self.record else {
+            return;
+        };
+        let mut writer = record.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(writer, "{}", entry) {
+            warn!("Failed to record request: {}", e);
+        }
+    }
+}
+
+impl fmt::Debug for Mock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mock")
+            .field("routes", &self.routes)
+            .field("record", &self.record.is_some())
+            .finish()
+    }
+}
+
+impl MockRoute {
+    fn compile(spec: RouteSpec, base: &Path) -> Result<Self> {
+        let method = spec
+            .method
+            .map(|m| {
+                Method::from_str(&m.to_uppercase()).map_err(|_| anyhow!("Invalid method {}", m))
+            })
+            .transpose()?;
+        let pattern = spec.path.parse()?;
+        let status = StatusCode::from_u16(spec.status)
+            .map_err(|_| anyhow!("Invalid status {}", spec.status))?;
+        let mut headers = spec
+            .headers
+            .into_iter()
+            .map(|(name, value)| {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| anyhow!("Invalid header name {}", name))?;
+                Ok((name, value))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let has_content_type = headers.iter().any(|(name, _)| name == header::CONTENT_TYPE);
+
+        let body = match (spec.body, spec.body_file) {
+            (Some(_), Some(_)) => bail!("Use either body or body_file, not both"),
+            (Some(body), None) => {
+                if !has_content_type {
+                    let content_type = "text/plain; charset=utf-8";
+                    headers.push((header::CONTENT_TYPE, content_type.to_string()));
+                }
+                MockBody::Template(body)
+            }
+            (None, Some(file)) => {
+                let file = base.join(file);
+                let content = std::fs::read(&file)
+                    .with_context(|| format!("Failed to read {}", file.display()))?;
+                if !has_content_type {
+                    let content_type = mime_guess::from_path(&file).first_or_octet_stream();
+                    headers.push((header::CONTENT_TYPE, content_type.to_string()));
+                }
+                match String::from_utf8(content) {
+                    Ok(text) => MockBody::Template(text),
+                    Err(e) => MockBody::Bytes(e.into_bytes()),
+                }
+            }
+            (None, None) => MockBody::Bytes(Vec::new()),
+        };
+        let delay = spec
+            .delay
+            .map(|d| duration_str::parse(&d).map_err(|_| anyhow!("Invalid delay {}", d)))
+            .transpose()?;
+        Ok(Self {
+            method,
+            path: spec.path,
+            pattern,
+            status,
+            headers,
+            body,
+            delay,
+        })
+    }
+
+    fn respond(&self, params: &HashMap<String, String>) -> Response<Body> {
+        let mut response = Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            match HeaderValue::from_str(&render(value, params)) {
+                Ok(value) => response = response.header(name, value),
+                Err(_) => warn!("Skipping header {}: invalid value after templating", name),
+            }
+        }
+        let body = match &self.body {
+            MockBody::Template(template) => Body::from(render(template, params)),
+            MockBody::Bytes(bytes) => Body::from(bytes.clone()),
+        };
+        response.body(body).unwrap()
+    }
+}
+
+impl FromStr for PathPattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some(rest) = s.strip_prefix('/') else {
+            bail!("Path {} must start with /", s);
+        };
+        let parts: Vec<&str> = rest.split('/').collect();
+        let mut segments = Vec::with_capacity(parts.len());
+        for (i, part) in parts.iter().enumerate() {
+            let segment = if let Some(name) = part.strip_prefix(':') {
+                Segment::Param(name.to_string())
+            } else if let Some(name) = part.strip_prefix('*') {
+                if i + 1 != parts.len() {
+                    bail!("Wildcard *{} must be the last segment of {}", name, s);
+                }
+                Segment::Rest(name.to_string())
+            } else {
+                Segment::Literal(part.to_string())
+            };
+            if matches!(&segment, Segment::Param(n) | Segment::Rest(n) if n.is_empty()) {
+                bail!("Unnamed parameter in {}", s);
+            }
+            segments.push(segment);
+        }
+        Ok(Self(segments))
+    }
+}
+
+impl PathPattern {
+    /// The decoded path parameters when `path` matches.
+    fn matches(&self, path: &str) -> Option<HashMap<String, String>> {
+        let decode = |s: &str| percent_decode_str(s).decode_utf8_lossy().to_string();
+        let mut parts = path.strip_prefix('/')?.split('/');
+        let mut params = HashMap::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Rest(name) => {
+                    let rest: Vec<&str> = parts.by_ref().collect();
+                    params.insert(name.clone(), decode(&rest.join("/")));
+                }
+                Segment::Param(name) => {
+                    let part = parts.next().filter(|p| !p.is_empty())?;
+                    params.insert(name.clone(), decode(part));
+                }
+                Segment::Literal(literal) => {
+                    if decode(parts.next()?) != *literal {
+                        return None;
+                    }
+                }
+            }
+        }
+        parts.next().is_none().then_some(params)
+    }
+}
+
+/// Replace `{{name}}` with path parameters; unknown names are left as is.
+fn render(template: &str, params: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        let placeholder = &rest[start..start + len + 2];
+        out.push_str(&rest[..start]);
+        match params.get(placeholder[2..len].trim()) {
+            Some(value) => out.push_str(value),
+            None => out.push_str(placeholder),
+        }
+        rest = &rest[start + len + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+async fn mock_handler(State(mock): State<Arc<Mock>>, req: Request) -> Response<Body> {
+    let (parts, body) = req.into_parts();
+    let body = match to_bytes(body, MAX_BODY).await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Failed to read request body: {}", e);
+            return Response::builder()
+                .status(StatusCode::PAYLOAD_TOO_LARGE)
+                .body(Body::empty())
+                .unwrap();
+        }
+    };
+    let path = parts.uri.path();
+    let matched = mock.find(&parts.method, path);
+
+    let mut headers = BTreeMap::new();
+    for (name, value) in &parts.headers {
+        let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+        headers
+            .entry(name.as_str().to_string())
+            .and_modify(|v: &mut String| {
+                v.push_str(", ");
+                v.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    let status = matched
+        .as_ref()
+        .map_or(StatusCode::NOT_FOUND, |(route, _)| route.status);
+    mock.record(serde_json::json!({
+        "time": Local::now().to_rfc3339(),
+        "method": parts.method.as_str(),
+        "path": path,
+        "query": parts.uri.query(),
+        "headers": headers,
+        "body": String::from_utf8_lossy(&body),
+        "route": matched.as_ref().map(|(route, _)| &route.path),
+        "status": status.as_u16(),
+    }));
+
+    let Some((route, params)) = matched else {
+        warn!("No mock route for {} {}", parts.method, path);
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from(format!(
+                "No mock route for {} {}",
+                parts.method, path
+            )))
+            .unwrap();
+    };
+    if let Some(delay) = route.delay {
+        tokio::time::sleep(delay).await;
+    }
+    route.respond(&params)
+}
+
+#[cfg(test)]
+mod tests {
+    use tower::ServiceExt;
+
+    use super::*;
+
+    const ROUTES: &str = r#"
+routes:
+  - method: get
+    path: /users/:id
+    headers:
+      content-type: application/json
+      x-user: "{{ id }}"
+    body: '{"id": "{{id}}", "name": "{{name}}"}'
+  - method: POST
+    path: /users
+    status: 201
+    delay: 50ms
+  - path: /static/*file
+    body_file: hello.txt
+"#;
+
+    fn fixture() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("routes.yaml"), ROUTES).unwrap();
+        std::fs::write(dir.path().join("hello.txt"), "hello {{file}}").unwrap();
+        dir
+    }
+
+    async fn call(app: &Router, method: &str, uri: &str) -> (Response<Body>, String) {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from("payload"))
+            .unwrap();
+        let response = app.clone().oneshot(req).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let body = to_bytes(body, usize::MAX).await.unwrap();
+        (
+            Response::from_parts(parts, Body::empty()),
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_path_pattern() {
+        let pattern: PathPattern = "/users/:id/posts".parse().unwrap();
+        let params = pattern.matches("/users/a%20b/posts").unwrap();
+        assert_eq!(params["id"], "a b");
+        assert!(pattern.matches("/users//posts").is_none());
+        assert!(pattern.matches("/users/1/posts/2").is_none());
+        assert!(pattern.matches("/users/1").is_none());
+
+        let pattern: PathPattern = "/static/*path".parse().unwrap();
+        assert_eq!(
+            pattern.matches("/static/css/app.css").unwrap()["path"],
+            "css/app.css"
+        );
+        let root: PathPattern = "/".parse().unwrap();
+        assert!(root.matches("/").is_some());
+        assert!(root.matches("/x").is_none());
+
+        assert!("users".parse::<PathPattern>().is_err());
+        assert!("/*rest/more".parse::<PathPattern>().is_err());
+        assert!("/users/:".parse::<PathPattern>().is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let params = HashMap::from([("id".to_string(), "7".to_string())]);
+        assert_eq!(render("id={{id}} {{ id }}", &params), "id=7 7");
+        assert_eq!(render("{{other}} {{id", &params), "{{other}} {{id");
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("routes.yaml");
+        for bad in [
+            "routes:\n  - path: /a\n    status: 1000\n",
+            "routes:\n  - path: /a\n    body: x\n    body_file: y\n",
+            "routes:\n  - path: /a\n    delay: soon\n",
+            "routes:\n  - path: /a\n    method: \"GET POST\"\n",
+            "routes:\n  - path: /a\n    unknown: 1\n",
+        ] {
+            std::fs::write(&path, bad).unwrap();
+            assert!(Mock::load(&path, None).is_err(), "{}", bad);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_router() {
+        let dir = fixture();
+        let record = dir.path().join("requests.jsonl");
+        let mock = Mock::load(
+            &dir.path().join("routes.yaml"),
+            Some(record.to_str().unwrap()),
+        )
+        .unwrap();
+        let app = mock_router(mock);
+
+        let (s, body) = call(&app, "GET", "/users/42").await;
+        assert_eq!(s.status(), StatusCode::OK);
+        assert_eq!(s.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(s.headers()["x-user"], "42");
+        assert_eq!(body, r#"{"id": "42", "name": "{{name}}"}"#);
+
+        let started = std::time::Instant::now();
+        let (s, body) = call(&app, "POST", "/users").await;
+        assert_eq!(s.status(), StatusCode::CREATED);
+        assert!(body.is_empty());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        let (s, body) = call(&app, "DELETE", "/static/a/b.txt").await;
+        assert_eq!(s.status(), StatusCode::OK);
+        assert_eq!(s.headers()[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(body, "hello a/b.txt");
+
+        let (s, body) = call(&app, "POST", "/users/42").await;
+        assert_eq!(s.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body, "No mock route for POST /users/42");
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&record)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0]["method"], "GET");
+        assert_eq!(lines[0]["route"], "/users/:id");
+        assert_eq!(lines[0]["body"], "payload");
+        assert_eq!(lines[3]["route"], serde_json::Value::Null);
+        assert_eq!(lines[3]["status"], 404);
+    }
+}
@@ -0,0 +1,1655 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::body::Body;
+use axum::extract::{DefaultBodyLimit, Path, Query, State};
+use axum::http::{header, HeaderMap, Response, StatusCode, Uri};
+use axum::routing::{get, post, put};
+use axum::{middleware, Router};
+use log::{info, warn};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::CorsLayer;
+use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
+use tracing::Level;
+
+use access_log::AccessLog;
+use archive::{archive_response, ArchiveFormat};
+use auth::{auth_middleware, BasicAuth};
+use file::{guess_content_type, precompressed_variant, serve_file};
+use ip_filter::{ip_filter_middleware, IpFilter};
+use layers::{cors_layer, weaken_etag, Compressible};
+use listing::{listing_response, ListingOptions};
+use metrics::{admin_router, Metrics, MetricsLayer};
+use proxy::{proxy_middleware, Proxy};
+use resolve::resolve_path;
+use serve::{shutdown_signal, Activity, Listener, Server};
+use tls::tls_acceptor;
+use watch::{inject_reload_script, LiveReload, RELOAD_PATH};
+
+use crate::{BindAddr, HttpServeOpts};
+
+pub use mock::http_mock;
+
+mod access_log;
+mod archive;
+mod auth;
+mod conditional;
+mod file;
+mod ip_filter;
+mod layers;
+mod listing;
+mod metrics;
+mod mock;
+mod proxy;
+mod range;
+mod resolve;
+mod serve;
+mod tls;
+mod upload;
+mod watch;
+
+#[derive(Debug)]
+struct HttpServerState {
+    // canonical form of `opts.dir`
+    path: PathBuf,
+    activity: Arc<Activity>,
+    metrics: Arc<Metrics>,
+    access_log: Option<Arc<AccessLog>>,
+    auth: Option<Arc<BasicAuth>>,
+    ip_filter: Option<Arc<IpFilter>>,
+    cors: Option<CorsLayer>,
+    live_reload: Option<Arc<LiveReload>>,
+    proxy: Option<Arc<Proxy>>,
+    opts: HttpServeOpts,
+}
+
+impl HttpServerState {
+    fn try_new(opts: HttpServeOpts) -> Result<Self> {
+        let access_log = match &opts.access_log {
+            Some(path) => Some(Arc::new(AccessLog::open(path, opts.access_log_format)?)),
+            None => None,
+        };
+        let path = opts.dir.canonicalize()?;
+        let live_reload = if opts.watch {
+            let ignore = opts
+                .access_log
+                .iter()
+                .filter_map(|log| std::path::Path::new(log).canonicalize().ok())
+                .collect();
+            Some(Arc::new(LiveReload::new(&path, ignore)?))
+        } else {
+            None
+        };
+        let activity = Arc::new(Activity::default());
+        Ok(Self {
+            path,
+            metrics: Arc::new(Metrics::new(activity.clone())),
+            activity,
+            access_log,
+            auth: BasicAuth::new(&opts)?.map(Arc::new),
+            ip_filter: IpFilter::new(&opts).map(Arc::new),
+            cors: cors_layer(&opts)?,
+            live_reload,
+            proxy: Proxy::new(&opts).map(Arc::new),
+            opts,
+        })
+    }
+}
+
+pub async fn http_server(opts: HttpServeOpts) -> Result<()> {
+    let tls = tls_acceptor(&opts)?;
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let mut listeners = Vec::new();
+    for bind in &opts.bind {
+        listeners.extend(Listener::bind(bind, opts.port).await?);
+    }
+    for listener in &listeners {
+        info!(
+            "Serving {} on {}://{}",
+            opts.dir.display(),
+            scheme,
+            listener.local_addr()
+        );
+    }
+
+    let admin = match opts.admin_port {
+        Some(port) => Some(bind_admin(&opts.bind, port).await?),
+        None => None,
+    };
+
+    let idle_exit = opts.idle_exit;
+    let shutdown_timeout = opts.shutdown_timeout;
+    let state = HttpServerState::try_new(opts)?;
+    let activity = state.activity.clone();
+    let metrics = state.metrics.clone();
+    let live_reload = state.live_reload.clone();
+    let server = Server {
+        listeners,
+        tls,
+        activity: activity.clone(),
+        shutdown_timeout,
+        router: router(state),
+    };
+    let (stop_tx, mut stop_rx) = tokio::sync::watch::channel(false);
+    let main = server.run(async move {
+        match idle_exit {
+            Some(idle) => tokio::select! {
+                _ = shutdown_signal() => {}
+                _ = activity.idle(idle) => info!("No requests for {:?}, exiting", idle),
+            },
+            None => shutdown_signal().await,
+        }
+        if let Some(live_reload) = live_reload {
+            live_reload.close();
+        }
+        stop_tx.send_replace(true);
+    });
+    let Some(listeners) = admin else {
+        return main.await;
+    };
+    // admin connections do not count as activity for --idle-exit
+    let admin = Server {
+        listeners,
+        tls: None,
+        activity: Arc::new(Activity::default()),
+        shutdown_timeout,
+        router: admin_router(metrics),
+    };
+    let stopped = async move {
+        let _ = stop_rx.wait_for(|stopped| *stopped).await;
+    };
+    tokio::try_join!(main, admin.run(stopped))?;
+    Ok(())
+}
+
+/// Bind `--admin-port` on the hosts of the TCP `--bind` addresses, or on
+/// localhost when the files are only served on unix sockets.
+async fn bind_admin(binds: &[BindAddr], port: u16) -> Result<Vec<Listener>> {
+    let mut hosts: Vec<&str> = Vec::new();
+    for bind in binds {
+        if let BindAddr::Tcp { host, .. } = bind {
+            if !hosts.contains(&host.as_str()) {
+                hosts.push(host);
+            }
+        }
+    }
+    if hosts.is_empty() {
+        hosts.push("127.0.0.1");
+    }
+    let mut listeners = Vec::new();
+    for host in hosts {
+        let addr = BindAddr::Tcp {
+            host: host.to_string(),
+            port: Some(port),
+        };
+        listeners.extend(Listener::bind(&addr, port).await?);
+    }
+    for listener in &listeners {
+        info!(
+            "Serving /healthz and /metrics on http://{}",
+            listener.local_addr()
+        );
+    }
+    Ok(listeners)
+}
+
+fn router(state: HttpServerState) -> Router {
+    let upload = state.opts.upload;
+    // axum router
+    let mut router = Router::new()
+        //http://localhost:8080/ visit root index
+        .route("/", get(index_handler))
+        .route("/*path", get(file_handler));
+    if upload {
+        // upload limits are enforced while streaming, see `upload::write_upload`
+        router = router
+            .route("/", post(upload::form_handler))
+            .route(
+                "/*path",
+                put(upload::put_handler).post(upload::form_handler),
+            )
+            .layer(DefaultBodyLimit::disable());
+    }
+    if state.opts.watch {
+        router = router
+            .route(RELOAD_PATH, get(watch::events_handler))
+            .layer(middleware::map_response(inject_reload_script));
+    }
+    if !state.opts.no_compression {
+        router = router
+            .layer(CompressionLayer::new().compress_when(Compressible))
+            .layer(middleware::map_response(weaken_etag));
+    }
+    let access = state.access_log.clone();
+    let auth = state.auth.clone();
+    let ip_filter = state.ip_filter.clone();
+    let cors = state.cors.clone();
+    let proxy = state.proxy.clone();
+    let metrics = state.metrics.clone();
+    let admin_port = state.opts.admin_port;
+    let mut router = router.with_state(Arc::new(state));
+    if let Some(proxy) = proxy {
+        router = router.layer(middleware::from_fn_with_state(proxy, proxy_middleware));
+    }
+    // added after the proxy, so a `/` route cannot swallow them
+    if admin_port.is_none() {
+        router = router.merge(admin_router(metrics.clone()));
+    }
+    // proxied routes are protected too, CORS preflights carry no credentials
+    if let Some(auth) = auth {
+        router = router.layer(middleware::from_fn_with_state(auth, auth_middleware));
+    }
+    if let Some(cors) = cors {
+        router = router.layer(cors);
+    }
+    // rejected clients still show up in the access log
+    if let Some(filter) = ip_filter {
+        router = router.layer(middleware::from_fn_with_state(filter, ip_filter_middleware));
+    }
+    if let Some(log) = access {
+        router = router.layer(middleware::from_fn_with_state(log, access_log::access_log));
+    }
+    // every request gets a span, so log records from handlers carry the request
+    router.layer(MetricsLayer(metrics)).layer(
+        TraceLayer::new_for_http()
+            .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
+            .on_response(DefaultOnResponse::new().level(Level::DEBUG)),
+    )
+}
+
+async fn index_handler(
+    State(state): State<Arc<HttpServerState>>,
+    Query(query): Query<HashMap<String, String>>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response<Body>, Infallible> {
+    file_handler(
+        State(state),
+        Path(String::new()),
+        Query(query),
+        uri,
+        headers,
+    )
+    .await
+}
+
+async fn file_handler(
+    State(state): State<Arc<HttpServerState>>,
+    Path(path): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Response<Body>, Infallible> {
+    let full_path = match resolve_path(&state.path, &path, state.opts.follow_symlinks) {
+        Ok(full_path) => full_path,
+        Err(StatusCode::NOT_FOUND) => return Ok(not_found(&state, &path, &headers).await),
+        Err(status) => {
+            warn!("Rejected {}: {}", path, status);
+            return Ok(Response::builder()
+                .status(status)
+                .body(Body::from("Forbidden"))
+                .unwrap());
+        }
+    };
+    if !full_path.is_dir() {
+        return Ok(send_file(&state, &full_path, &query, &headers).await);
+    }
+
+    // an archive exposes everything a listing would
+    if let Some(format) = query.get("archive") {
+        if state.opts.no_listing {
+            return Ok(not_found(&state, &path, &headers).await);
+        }
+        let Some(format) = ArchiveFormat::from_query(format) else {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from("Unsupported archive format"))
+                .unwrap());
+        };
+        let name = full_path.file_name().map_or_else(
+            || "archive".to_string(),
+            |n| n.to_string_lossy().to_string(),
+        );
+        return Ok(archive_response(
+            full_path,
+            name,
+            format,
+            ListingOptions::from_query(&query).show_hidden,
+            state.opts.follow_symlinks,
+        ));
+    }
+
+    // directory links are relative, so the url must end with a slash
+    if !uri.path().ends_with('/') {
+        let location = match uri.query() {
+            Some(q) => format!("{}/?{}", uri.path(), q),
+            None => format!("{}/", uri.path()),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::MOVED_PERMANENTLY)
+            .header(header::LOCATION, location)
+            .body(Body::empty())
+            .unwrap());
+    }
+    if let Some(index) = index_file(&state, &path) {
+        return Ok(send_file(&state, &index, &query, &headers).await);
+    }
+    if state.opts.no_listing {
+        return Ok(not_found(&state, &path, &headers).await);
+    }
+    //遍历下游文件，并输出一个index.html显示目录文件
+    match listing_response(&full_path, uri.path(), &query, &headers, &state) {
+        Ok(response) => Ok(response),
+        Err(e) => {
+            warn!("Error reading directory: {}", e);
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(format!("Error reading directory: {}", e)))
+                .unwrap())
+        }
+    }
+}
+
+async fn send_file(
+    state: &HttpServerState,
+    full_path: &std::path::Path,
+    query: &HashMap<String, String>,
+    headers: &HeaderMap,
+) -> Response<Body> {
+    info!("Serving {}", full_path.display());
+    let variant = if state.opts.precompressed {
+        precompressed_variant(full_path, headers, state.opts.follow_symlinks)
+    } else {
+        None
+    };
+    let download = query.contains_key("download");
+    match serve_file(full_path, variant.as_ref(), download, headers).await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("Error reading file: {}", e);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(format!("Error reading file: {}", e)))
+                .unwrap()
+        }
+    }
+}
+
+/// The first `--index` file present in the directory at request path `dir`.
+fn index_file(state: &HttpServerState, dir: &str) -> Option<PathBuf> {
+    state
+        .opts
+        .index
+        .iter()
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| {
+            resolve_path(
+                &state.path,
+                &format!("{}/{}", dir, name),
+                state.opts.follow_symlinks,
+            )
+            .ok()
+        })
+        .find(|path| path.is_file())
+}
+
+/// Answer a request for something that does not exist: the root index in
+/// `--spa` mode, else the `--not-found` page or a plain message.
+async fn not_found(state: &HttpServerState, path: &str, headers: &HeaderMap) -> Response<Body> {
+    // paths with an extension are missing assets, not client side routes
+    let is_route = std::path::Path::new(path).extension().is_none();
+    if state.opts.spa && is_route {
+        if let Some(index) = index_file(state, "") {
+            return send_file(state, &index, &HashMap::new(), headers).await;
+        }
+    }
+    warn!("Rejected {}: {}", path, StatusCode::NOT_FOUND);
+    let page = match &state.opts.not_found {
+        Some(page) => match tokio::fs::read(page).await {
+            Ok(body) => Some((page, body)),
+            Err(e) => {
+                warn!("Error reading {}: {}", page, e);
+                None
+            }
+        },
+        None => None,
+    };
+    let builder = Response::builder().status(StatusCode::NOT_FOUND);
+    match page {
+        Some((page, body)) => builder
+            .header(
+                header::CONTENT_TYPE,
+                guess_content_type(std::path::Path::new(page))
+                    .unwrap_or_else(|| "text/html; charset=utf-8".to_string()),
+            )
+            .body(Body::from(body))
+            .unwrap(),
+        None => builder.body(Body::from("Not found file")).unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::body::to_bytes;
+    use clap::Parser;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn serve_opts(root: &std::path::Path, args: &[&str]) -> HttpServeOpts {
+        let root = root.to_str().unwrap();
+        HttpServeOpts::try_parse_from(["serve", "--dir", root].iter().chain(args)).unwrap()
+    }
+
+    fn test_state(root: &std::path::Path, args: &[&str]) -> HttpServerState {
+        HttpServerState::try_new(serve_opts(root, args)).unwrap()
+    }
+
+    async fn get(path: &str, query: &[(&str, &str)]) -> (Response<Body>, Vec<u8>) {
+        get_in(".", path, query).await
+    }
+
+    async fn get_in(
+        root: impl Into<PathBuf>,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> (Response<Body>, Vec<u8>) {
+        get_with(root, path, query, HeaderMap::new()).await
+    }
+
+    async fn get_with(
+        root: impl Into<PathBuf>,
+        path: &str,
+        query: &[(&str, &str)],
+        headers: HeaderMap,
+    ) -> (Response<Body>, Vec<u8>) {
+        let state = test_state(&root.into(), &[]);
+        let mut uri = format!("/{}", path.trim_start_matches('/'));
+        if !query.is_empty() {
+            let query: Vec<_> = query.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+            uri = format!("{}?{}", uri, query.join("&"));
+        }
+        let mut req = axum::http::Request::get(uri).body(Body::empty()).unwrap();
+        *req.headers_mut() = headers;
+        let s = router(state).oneshot(req).await.unwrap();
+        let (parts, body) = s.into_parts();
+        let body = to_bytes(body, usize::MAX).await.unwrap().to_vec();
+        (Response::from_parts(parts, Body::empty()), body)
+    }
+
+    #[tokio::test]
+    async fn test_file_handler_not_found() {
+        let (s, body) = get("test.txt", &[]).await;
+        assert_eq!(s.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body, b"Not found file");
+    }
+
+    #[tokio::test]
+    async fn test_file_handler_found() {
+        let (s, body) = get("Cargo.toml", &[]).await;
+        assert_eq!(s.status(), StatusCode::OK);
+        assert!(String::from_utf8_lossy(&body).contains("[package]"));
+        assert_eq!(
+            s.headers()[header::CONTENT_TYPE],
+            "text/x-toml; charset=utf-8"
+        );
+        assert_eq!(
+            s.headers()[header::CONTENT_LENGTH],
+            std::fs::metadata("Cargo.toml").unwrap().len().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_handler_dir() {
+        // the crate root has an index.html, so list a directory without one
+        let (s, body) = get_in("src", "/", &[]).await;
+        assert_eq!(s.status(), StatusCode::OK);
+        assert!(String::from_utf8_lossy(&body).contains("html"));
+    }
+
+    #[tokio::test]
+    async fn test_file_handler_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let png: Vec<u8> = b"\x89PNG\r\n\x1a\n"
+            .iter()
+            .copied()
+            .chain((0..=255u8).cycle().take(4096))
+            .collect();
+        std::fs::write(dir.path().join("logo.png"), &png).unwrap();
+        std::fs::write(dir.path().join("logo"), &png).unwrap();
+
+        let (s, body) = get_in(dir.path(), "logo.png", &[]).await;
+        assert_eq!(s.headers()[header::CONTENT_TYPE], "image/png");
+        assert_eq!(body, png);
+
+        // no extension: the type is sniffed from the content
+        let (s, body) = get_in(dir.path(), "logo", &[]).await;
+        assert_eq!(s.headers()[header::CONTENT_TYPE], "image/png");
+        assert_eq!(body, png);
+    }
+
+    #[tokio::test]
+    async fn test_file_handler_download() {
+        let (s, _) = get("Cargo.toml", &[]).await;
+        assert_eq!(
+            s.headers()[header::CONTENT_DISPOSITION],
+            "inline; filename=\"Cargo.toml\""
+        );
+        let (s, _) = get("Cargo.toml", &[("download", "")]).await;
+        assert_eq!(
+            s.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"Cargo.toml\""
+        );
+    }
+
+    fn range_headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.clone(), v.parse().unwrap()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_file_handler_range() {
+        let full = std::fs::read("Cargo.toml").unwrap();
+        let len = full.len();
+        let headers = range_headers(&[(header::RANGE, "bytes=0-100")]);
+        let (s, body) = get_with(".", "Cargo.toml", &[], headers).await;
+        assert_eq!(s.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            s.headers()[header::CONTENT_RANGE],
+            format!("bytes 0-100/{}", len)
+        );
+        assert_eq!(s.headers()[header::CONTENT_LENGTH], "101");
+        assert_eq!(body, &full[..=100]);
+
+        let headers = range_headers(&[(header::RANGE, &format!("bytes={}-", len))]);
+        let (s, _) = get_with(".", "Cargo.toml", &[], headers).await;
+        assert_eq!(s.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(
+            s.headers()[header::CONTENT_RANGE],
+            format!("bytes */{}", len)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_handler_multi_range() {
+        let full = std::fs::read("Cargo.toml").unwrap();
+        let headers = range_headers(&[(header::RANGE, "bytes=0-9,20-29")]);
+        let (s, body) = get_with(".", "Cargo.toml", &[], headers).await;
+        assert_eq!(s.status(), StatusCode::PARTIAL_CONTENT);
+        let content_type = s.headers()[header::CONTENT_TYPE].to_str().unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+        assert_eq!(s.headers()[header::CONTENT_LENGTH], body.len().to_string());
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.starts_with(&format!("--{}\r\n", boundary)));
+        assert!(body.ends_with(&format!("\r\n--{}--\r\n", boundary)));
+        assert!(body.contains(&format!("Content-Range: bytes 20-29/{}", full.len())));
+        assert!(body.contains(std::str::from_utf8(&full[20..30]).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_file_handler_conditional() {
+        let (s, _) = get("Cargo.toml", &[]).await;
+        let etag = s.headers()[header::ETAG].to_str().unwrap().to_string();
+        let last_modified = s.headers()[header::LAST_MODIFIED]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let headers = range_headers(&[(header::IF_NONE_MATCH, &etag)]);
+        let (s, body) = get_with(".", "Cargo.toml", &[], headers).await;
+        assert_eq!(s.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(s.headers()[header::ETAG], etag.as_str());
+        assert!(body.is_empty());
+
+        let headers = range_headers(&[(header::IF_MODIFIED_SINCE, &last_modified)]);
+        let (s, _) = get_with(".", "Cargo.toml", &[], headers).await;
+        assert_eq!(s.status(), StatusCode::NOT_MODIFIED);
+
+        // a stale If-Range turns the range request into a full response
+        let headers = range_headers(&[
+            (header::RANGE, "bytes=0-10"),
+            (header::IF_RANGE, "\"stale\""),
+        ]);
+        let (s, _) = get_with(".", "Cargo.toml", &[], headers).await;
+        assert_eq!(s.status(), StatusCode::OK);
+        let headers = range_headers(&[(header::RANGE, "bytes=0-10"), (header::IF_RANGE, &etag)]);
+        let (s, _) = get_with(".", "Cargo.toml", &[], headers).await;
+        assert_eq!(s.status(), StatusCode::PARTIAL_CONTENT);
+    }
+
+    async fn request(state: HttpServerState, uri: &str) -> (StatusCode, Vec<u8>) {
+        let req = axum::http::Request::get(uri).body(Body::empty()).unwrap();
+        let s = router(state).oneshot(req).await.unwrap();
+        let status = s.status();
+        (
+            status,
+            to_bytes(s.into_body(), usize::MAX).await.unwrap().to_vec(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_router_path_traversal() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+        std::fs::write(root.join("a.txt"), "a").unwrap();
+        let state = || test_state(&root, &[]);
+
+        let (status, body) = request(state(), "/a.txt").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"a");
+        let (status, body) = request(state(), "/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(String::from_utf8_lossy(&body).contains("a.txt"));
+
+        for uri in [
+            "/../secret.txt",
+            "/%2e%2e/secret.txt",
+            "/%2E%2E%2Fsecret.txt",
+            "/sub/../../secret.txt",
+            "/..%5csecret.txt",
+        ] {
+            let (status, body) = request(state(), uri).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{}", uri);
+            assert!(
+                !String::from_utf8_lossy(&body).contains("secret"),
+                "{}",
+                uri
+            );
+        }
+        // absolute paths are resolved against the served directory
+        let secret = dir.path().join("secret.txt");
+        let (status, _) = request(state(), &format!("/{}", secret.display())).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_router_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("secret.txt"), root.join("link.txt")).unwrap();
+
+        let state = test_state(&root, &[]);
+        let (status, _) = request(state, "/link.txt").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let state = test_state(&root, &["--follow-symlinks"]);
+        let (status, body) = request(state, "/link.txt").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"secret");
+    }
+
+    #[tokio::test]
+    async fn test_router_listing() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("sub/<nested>")).unwrap();
+        std::fs::write(dir.path().join("sub/a b.txt"), "a").unwrap();
+        std::fs::write(dir.path().join("sub/.env"), "").unwrap();
+
+        let (s, _) = get_in(dir.path(), "sub", &[("sort", "size")]).await;
+        assert_eq!(s.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(s.headers()[header::LOCATION], "/sub/?sort=size");
+
+        let (s, body) = get_in(dir.path(), "sub/", &[]).await;
+        assert_eq!(s.status(), StatusCode::OK);
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.contains("<a href=\"a%20b.txt\">a b.txt</a>"));
+        assert!(body.contains("<a href=\"%3Cnested%3E/\">&lt;nested&gt;/</a>"));
+        assert!(body.contains("<a href=\"../\">"));
+        assert!(!body.contains(".env"));
+        assert!(!body.contains(&dir.path().display().to_string()));
+
+        let (_, body) = get_in(dir.path(), "sub/", &[("hidden", "true")]).await;
+        assert!(String::from_utf8(body).unwrap().contains(".env"));
+
+        let headers = range_headers(&[(header::ACCEPT, "application/json")]);
+        let (s, body) = get_with(dir.path(), "sub/", &[], headers).await;
+        assert_eq!(s.headers()[header::CONTENT_TYPE], "application/json");
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["path"], "/sub/");
+        assert_eq!(json["entries"][0]["name"], "<nested>");
+        assert_eq!(json["entries"][0]["is_dir"], true);
+        assert_eq!(json["entries"][1]["href"], "a%20b.txt");
+        assert_eq!(json["entries"][1]["size"], 1);
+    }
+
+    async fn send(
+        state: HttpServerState,
+        req: axum::http::Request<Body>,
+    ) -> (Response<Body>, Vec<u8>) {
+        let s = router(state).oneshot(req).await.unwrap();
+        let (parts, body) = s.into_parts();
+        let body = to_bytes(body, usize::MAX).await.unwrap().to_vec();
+        (Response::from_parts(parts, Body::empty()), body)
+    }
+
+    fn put_request(uri: &str, body: &'static str) -> axum::http::Request<Body> {
+        axum::http::Request::put(uri)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    fn leftover_temp_files(dir: &std::path::Path) -> usize {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .filter(|e| {
+                let name = e.as_ref().unwrap().file_name();
+                name.to_string_lossy().starts_with(".upload-")
+            })
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_upload_put() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join("sub")).unwrap();
+
+        let (s, _) = send(test_state(root, &[]), put_request("/sub/a.txt", "a")).await;
+        assert_eq!(s.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let (s, _) = send(
+            test_state(root, &["--upload"]),
+            put_request("/sub/a.txt", "a"),
+        )
+        .await;
+        assert_eq!(s.status(), StatusCode::CREATED);
+        assert_eq!(s.headers()[header::LOCATION], "/sub/a.txt");
+        assert_eq!(std::fs::read(root.join("sub/a.txt")).unwrap(), b"a");
+
+        let (s, _) = send(
+            test_state(root, &["--upload"]),
+            put_request("/sub/a.txt", "b"),
+        )
+        .await;
+        assert_eq!(s.status(), StatusCode::CONFLICT);
+        assert_eq!(std::fs::read(root.join("sub/a.txt")).unwrap(), b"a");
+
+        let state = test_state(root, &["--upload", "--overwrite", "replace"]);
+        let (s, _) = send(state, put_request("/sub/a.txt", "b")).await;
+        assert_eq!(s.status(), StatusCode::NO_CONTENT);
+        assert_eq!(std::fs::read(root.join("sub/a.txt")).unwrap(), b"b");
+
+        let state = test_state(root, &["--upload", "--overwrite", "rename"]);
+        let (s, _) = send(state, put_request("/sub/a.txt", "c")).await;
+        assert_eq!(s.status(), StatusCode::CREATED);
+        assert_eq!(s.headers()[header::LOCATION], "/sub/a%20(1).txt");
+        assert_eq!(std::fs::read(root.join("sub/a (1).txt")).unwrap(), b"c");
+
+        let (s, _) = send(
+            test_state(root, &["--upload"]),
+            put_request("/../a.txt", "a"),
+        )
+        .await;
+        assert_eq!(s.status(), StatusCode::FORBIDDEN);
+        let (s, _) = send(test_state(root, &["--upload"]), put_request("/sub", "a")).await;
+        assert_eq!(s.status(), StatusCode::CONFLICT);
+        assert_eq!(leftover_temp_files(&root.join("sub")), 0);
+    }
+
+    #[tokio::test]
+    async fn test_upload_too_large() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let state = || test_state(root, &["--upload", "--max-upload-size", "4"]);
+
+        let (s, _) = send(state(), put_request("/big.txt", "12345")).await;
+        assert_eq!(s.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // without a Content-Length the limit is enforced while streaming
+        let stream = futures_util::stream::iter(["123", "45"].map(Ok::<_, std::io::Error>));
+        let req = axum::http::Request::put("/big.txt")
+            .body(Body::from_stream(stream))
+            .unwrap();
+        let (s, _) = send(state(), req).await;
+        assert_eq!(s.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(!root.join("big.txt").exists());
+        assert_eq!(leftover_temp_files(root), 0);
+
+        // the limit covers all files of a multipart request together
+        let part = |name: &str| {
+            format!(
+                "--XyZ\r\n\
+                Content-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\r\n\
+                123\r\n",
+                name
+            )
+        };
+        let body = format!(
+            "{}{}{}--XyZ--\r\n",
+            part("a.txt"),
+            part("b.txt"),
+            part("c.txt")
+        );
+        let req = axum::http::Request::post("/")
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=XyZ")
+            .body(Body::from(body))
+            .unwrap();
+        let state = test_state(root, &["--upload", "--max-upload-size", "5"]);
+        let (s, _) = send(state, req).await;
+        assert_eq!(s.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(!root.join("b.txt").exists());
+        assert!(!root.join("c.txt").exists());
+        assert_eq!(leftover_temp_files(root), 0);
+    }
+
+    #[tokio::test]
+    async fn test_upload_multipart() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let body = "--XyZ\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\r\n\
+            aaa\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"comment\"\r\n\r\n\
+            ignored\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"b.bin\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n\
+            bbb\r\n\
+            --XyZ--\r\n";
+        let request = |accept: &str| {
+            axum::http::Request::post("/")
+                .header(header::CONTENT_TYPE, "multipart/form-data; boundary=XyZ")
+                .header(header::ACCEPT, accept)
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let state = test_state(root, &["--upload"]);
+        let (s, body) = send(state, request("application/json")).await;
+        assert_eq!(s.status(), StatusCode::CREATED);
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["files"], serde_json::json!(["a.txt", "b.bin"]));
+        assert_eq!(std::fs::read(root.join("a.txt")).unwrap(), b"aaa");
+        assert_eq!(std::fs::read(root.join("b.bin")).unwrap(), b"bbb");
+
+        let state = test_state(root, &["--upload", "--overwrite", "replace"]);
+        let (s, _) = send(state, request("text/html")).await;
+        assert_eq!(s.status(), StatusCode::SEE_OTHER);
+        assert_eq!(s.headers()[header::LOCATION], "/");
+
+        let (_, body) = get_with(root, "/", &[], HeaderMap::new()).await;
+        assert!(!String::from_utf8(body).unwrap().contains("<form"));
+        let (_, body) = send(
+            test_state(root, &["--upload"]),
+            axum::http::Request::get("/").body(Body::empty()).unwrap(),
+        )
+        .await;
+        assert!(String::from_utf8(body)
+            .unwrap()
+            .contains("multipart/form-data"));
+    }
+
+    fn compression_fixture() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "compress me ".repeat(100)).unwrap();
+        std::fs::write(dir.path().join("a.zip"), vec![b'P'; 1200]).unwrap();
+        dir
+    }
+
+    fn encoded_get(uri: &str, encoding: &str) -> axum::http::Request<Body> {
+        axum::http::Request::get(uri)
+            .header(header::ACCEPT_ENCODING, encoding)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_compression() {
+        let dir = compression_fixture();
+        for (accept, expected) in [("gzip", "gzip"), ("br", "br"), ("zstd, gzip;q=0.5", "zstd")] {
+            let (s, body) = send(test_state(dir.path(), &[]), encoded_get("/a.txt", accept)).await;
+            assert_eq!(s.status(), StatusCode::OK);
+            assert_eq!(s.headers()[header::CONTENT_ENCODING], expected);
+            assert_eq!(s.headers()[header::VARY], "accept-encoding");
+            assert!(s.headers().get(header::CONTENT_LENGTH).is_none());
+            assert!(s.headers()[header::ETAG]
+                .to_str()
+                .unwrap()
+                .starts_with("W/\""));
+            assert!(body.len() < 1200);
+        }
+
+        // the weak tag still validates the cached copy
+        let (s, _) = send(test_state(dir.path(), &[]), encoded_get("/a.txt", "gzip")).await;
+        let mut req = encoded_get("/a.txt", "gzip");
+        req.headers_mut()
+            .insert(header::IF_NONE_MATCH, s.headers()[header::ETAG].clone());
+        let (s, _) = send(test_state(dir.path(), &[]), req).await;
+        assert_eq!(s.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn test_compression_skipped() {
+        let dir = compression_fixture();
+        // identity when not accepted, for archives, ranges and when disabled
+        let cases = [
+            (
+                test_state(dir.path(), &[]),
+                encoded_get("/a.txt", "identity"),
+            ),
+            (test_state(dir.path(), &[]), encoded_get("/a.zip", "gzip")),
+            (
+                test_state(dir.path(), &["--no-compression"]),
+                encoded_get("/a.txt", "gzip"),
+            ),
+        ];
+        for (state, req) in cases {
+            let (s, body) = send(state, req).await;
+            assert_eq!(s.status(), StatusCode::OK);
+            assert!(s.headers().get(header::CONTENT_ENCODING).is_none());
+            assert_eq!(body.len(), 1200);
+        }
+
+        let mut req = encoded_get("/a.txt", "gzip");
+        req.headers_mut()
+            .insert(header::RANGE, "bytes=0-9".parse().unwrap());
+        let (s, body) = send(test_state(dir.path(), &[]), req).await;
+        assert_eq!(s.status(), StatusCode::PARTIAL_CONTENT);
+        assert!(s.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(body, b"compress m");
+    }
+
+    #[tokio::test]
+    async fn test_cors() {
+        let dir = compression_fixture();
+        let origin_get = |origin: &str| {
+            axum::http::Request::get("/a.txt")
+                .header(header::ORIGIN, origin)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let (s, _) = send(test_state(dir.path(), &[]), origin_get("https://a.example")).await;
+        assert!(s
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+
+        let (s, _) = send(
+            test_state(dir.path(), &["--cors"]),
+            origin_get("https://a.example"),
+        )
+        .await;
+        assert_eq!(s.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(s.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS]
+            .to_str()
+            .unwrap()
+            .contains("content-range"));
+
+        let restricted = ["--cors", "--cors-origins", "https://a.example"];
+        let (s, _) = send(
+            test_state(dir.path(), &restricted),
+            origin_get("https://a.example"),
+        )
+        .await;
+        assert_eq!(
+            s.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://a.example"
+        );
+        let (s, _) = send(
+            test_state(dir.path(), &restricted),
+            origin_get("https://b.example"),
+        )
+        .await;
+        assert!(s
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+
+        let preflight = axum::http::Request::builder()
+            .method("OPTIONS")
+            .uri("/a.txt")
+            .header(header::ORIGIN, "https://a.example")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+            .body(Body::empty())
+            .unwrap();
+        let (s, _) = send(
+            test_state(dir.path(), &["--cors", "--cors-methods", "get,put"]),
+            preflight,
+        )
+        .await;
+        assert_eq!(s.status(), StatusCode::OK);
+        assert_eq!(s.headers()[header::ACCESS_CONTROL_ALLOW_METHODS], "GET,PUT");
+    }
+
+    fn site_fixture() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.html"), "<h1>home</h1>").unwrap();
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        std::fs::write(dir.path().join("docs/index.htm"), "<h1>docs</h1>").unwrap();
+        std::fs::create_dir(dir.path().join("files")).unwrap();
+        std::fs::write(dir.path().join("files/a.txt"), "a").unwrap();
+        std::fs::write(dir.path().join("404.html"), "<h1>gone</h1>").unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_index_files() {
+        let dir = site_fixture();
+        let (status, body) = request(test_state(dir.path(), &[]), "/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"<h1>home</h1>");
+        let (_, body) = request(test_state(dir.path(), &[]), "/docs/").await;
+        assert_eq!(body, b"<h1>docs</h1>");
+
+        // without index files directories are listed
+        let (status, body) = request(test_state(dir.path(), &["--index", ""]), "/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(String::from_utf8(body).unwrap().contains("index.html"));
+
+        let (status, _) = request(test_state(dir.path(), &["--no-listing"]), "/files/").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = request(test_state(dir.path(), &["--no-listing"]), "/docs/").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_spa_and_not_found_page() {
+        let dir = site_fixture();
+        let page = dir.path().join("404.html");
+        let page = page.to_str().unwrap();
+
+        let (status, body) = request(test_state(dir.path(), &["--spa"]), "/app/settings").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"<h1>home</h1>");
+        let (status, _) = request(test_state(dir.path(), &["--spa"]), "/app/missing.js").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = request(test_state(dir.path(), &[]), "/app/settings").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let req = axum::http::Request::get("/missing")
+            .body(Body::empty())
+            .unwrap();
+        let (s, body) = send(test_state(dir.path(), &["--not-found", page]), req).await;
+        assert_eq!(s.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            s.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(body, b"<h1>gone</h1>");
+    }
+
+    #[tokio::test]
+    async fn test_precompressed() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("app.js"), "console.log(1);".repeat(10)).unwrap();
+        std::fs::write(dir.path().join("app.js.br"), "brotli bytes").unwrap();
+        std::fs::write(dir.path().join("app.js.gz"), "gzip bytes").unwrap();
+        let args = ["--precompressed", "--no-compression"];
+
+        let (s, body) = send(
+            test_state(dir.path(), &args),
+            encoded_get("/app.js", "gzip, br"),
+        )
+        .await;
+        assert_eq!(s.headers()[header::CONTENT_ENCODING], "br");
+        assert_eq!(s.headers()[header::VARY], "accept-encoding");
+        assert!(s.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .contains("javascript"));
+        assert_eq!(body, b"brotli bytes");
+
+        let (s, body) = send(
+            test_state(dir.path(), &args),
+            encoded_get("/app.js", "br;q=0.5, gzip"),
+        )
+        .await;
+        assert_eq!(s.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(body, b"gzip bytes");
+
+        for (state, accept) in [
+            (test_state(dir.path(), &args), "identity"),
+            (test_state(dir.path(), &["--no-compression"]), "gzip, br"),
+        ] {
+            let (s, body) = send(state, encoded_get("/app.js", accept)).await;
+            assert!(s.headers().get(header::CONTENT_ENCODING).is_none());
+            assert_eq!(body.len(), 150);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_precompressed_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("app.js"), "console.log(1);").unwrap();
+        std::fs::write(dir.path().join("secret"), "secret").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("secret"), root.join("app.js.gz")).unwrap();
+
+        let state = test_state(&root, &["--precompressed", "--no-compression"]);
+        let (s, body) = send(state, encoded_get("/app.js", "gzip")).await;
+        assert!(s.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(body, b"console.log(1);");
+
+        let args = ["--precompressed", "--no-compression", "--follow-symlinks"];
+        let (s, body) = send(test_state(&root, &args), encoded_get("/app.js", "gzip")).await;
+        assert_eq!(s.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(body, b"secret");
+    }
+
+    #[tokio::test]
+    async fn test_watch_live_reload() {
+        use futures_util::StreamExt;
+
+        let dir = site_fixture();
+        let router = router(test_state(dir.path(), &["--watch"]));
+
+        let req = axum::http::Request::get("/").body(Body::empty()).unwrap();
+        let s = router.clone().oneshot(req).await.unwrap();
+        let etag = s.headers()[header::ETAG].to_str().unwrap().to_string();
+        assert!(etag.starts_with("W/\""));
+        let length: usize = s.headers()[header::CONTENT_LENGTH]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let body = to_bytes(s.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.len(), length);
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.starts_with("<h1>home</h1><script>"));
+        assert!(body.contains(watch::RELOAD_PATH));
+
+        // the weak tag still revalidates the page
+        let req = axum::http::Request::get("/")
+            .header(header::IF_NONE_MATCH, &etag)
+            .body(Body::empty())
+            .unwrap();
+        let s = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(s.status(), StatusCode::NOT_MODIFIED);
+
+        // plain files are left alone
+        let (status, body) = request(test_state(dir.path(), &["--watch"]), "/files/a.txt").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"a");
+
+        let req = axum::http::Request::get(watch::RELOAD_PATH)
+            .body(Body::empty())
+            .unwrap();
+        // the router owns the watcher, keep it alive while listening
+        let s = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(s.headers()[header::CONTENT_TYPE], "text/event-stream");
+        let mut events = s.into_body().into_data_stream();
+        std::fs::write(dir.path().join("files/b.txt"), "b").unwrap();
+        let event = tokio::time::timeout(std::time::Duration::from_secs(5), events.next())
+            .await
+            .expect("a reload event after a change")
+            .unwrap()
+            .unwrap();
+        let event = String::from_utf8(event.to_vec()).unwrap();
+        assert!(event.starts_with("event: reload\n"));
+        assert!(event.contains("b.txt"));
+    }
+
+    async fn upstream() -> SocketAddr {
+        use axum::extract::Request;
+        use axum::routing::{any, get};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        async fn echo(req: Request) -> axum::Json<serde_json::Value> {
+            let (parts, body) = req.into_parts();
+            let header = |name: &str| {
+                parts
+                    .headers
+                    .get(name)
+                    .map(|v| v.to_str().unwrap().to_string())
+            };
+            let body = to_bytes(body, usize::MAX).await.unwrap();
+            axum::Json(serde_json::json!({
+                "method": parts.method.as_str(),
+                "uri": parts.uri.to_string(),
+                "host": header("host"),
+                "forwarded_host": header("x-forwarded-host"),
+                "forwarded_proto": header("x-forwarded-proto"),
+                "forwarded_prefix": header("x-forwarded-prefix"),
+                "secret": header("x-secret"),
+                "authorization": header("authorization"),
+                "body": String::from_utf8(body.to_vec()).unwrap(),
+            }))
+        }
+
+        async fn upgrade(mut req: Request) -> Response<Body> {
+            let on_upgrade = hyper::upgrade::on(&mut req);
+            tokio::spawn(async move {
+                let mut io = hyper_util::rt::TokioIo::new(on_upgrade.await.unwrap());
+                let mut buf = [0u8; 4];
+                io.read_exact(&mut buf).await.unwrap();
+                io.write_all(&buf).await.unwrap();
+            });
+            Response::builder()
+                .status(StatusCode::SWITCHING_PROTOCOLS)
+                .header(header::CONNECTION, "upgrade")
+                .header(header::UPGRADE, "echo")
+                .body(Body::empty())
+                .unwrap()
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/echo/*rest", any(echo))
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                    "slow"
+                }),
+            )
+            .route(
+                "/redirect",
+                get(move || async move {
+                    Response::builder()
+                        .status(StatusCode::FOUND)
+                        .header(header::LOCATION, format!("http://{}/login", addr))
+                        .body(Body::empty())
+                        .unwrap()
+                }),
+            )
+            .route("/upgrade", get(upgrade));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_proxy() {
+        let addr = upstream().await;
+        let dir = site_fixture();
+        let route = format!("/api=http://{}", addr);
+        let state = || test_state(dir.path(), &["--proxy", &route, "--proxy-timeout", "200ms"]);
+
+        let req = axum::http::Request::post("/api/echo/a%20b?x=1")
+            .header(header::HOST, "rcli.test")
+            .header(header::CONNECTION, "x-secret")
+            .header("x-secret", "hop-by-hop")
+            .header(header::AUTHORIZATION, "Bearer upstream-token")
+            .body(Body::from("payload"))
+            .unwrap();
+        let (s, body) = send(state(), req).await;
+        assert_eq!(s.status(), StatusCode::OK);
+        let echo: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(echo["method"], "POST");
+        assert_eq!(echo["uri"], "/echo/a%20b?x=1");
+        assert_eq!(echo["host"], addr.to_string());
+        assert_eq!(echo["forwarded_host"], "rcli.test");
+        assert_eq!(echo["forwarded_proto"], "http");
+        assert_eq!(echo["forwarded_prefix"], "/api");
+        assert_eq!(echo["secret"], serde_json::Value::Null);
+        assert_eq!(echo["authorization"], "Bearer upstream-token");
+        assert_eq!(echo["body"], "payload");
+
+        // everything else is still served from the directory
+        let (status, body) = request(state(), "/files/a.txt").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"a");
+        let (status, _) = request(state(), "/apis").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let req = axum::http::Request::get("/api/redirect")
+            .body(Body::empty())
+            .unwrap();
+        let (s, _) = send(state(), req).await;
+        assert_eq!(s.status(), StatusCode::FOUND);
+        assert_eq!(s.headers()[header::LOCATION], "/api/login");
+
+        let (status, _) = request(state(), "/api/slow").await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_strips_basic_auth() {
+        let addr = upstream().await;
+        let route = format!("/api=http://{}", addr);
+        let args = ["--proxy", &route, "--auth", "alice:secret"];
+        use base64::Engine;
+
+        let encoded = base64::engine::general_purpose::STANDARD.encode("alice:secret");
+        let req = axum::http::Request::get("/api/echo/x")
+            .header(header::AUTHORIZATION, format!("Basic {}", encoded))
+            .body(Body::empty())
+            .unwrap();
+        let (s, body) = send(test_state(std::path::Path::new("."), &args), req).await;
+        assert_eq!(s.status(), StatusCode::OK);
+        let echo: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(echo["authorization"], serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_unavailable() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let route = format!("/api=http://{}", addr);
+        let (status, _) = request(
+            test_state(std::path::Path::new("."), &["--proxy", &route]),
+            "/api/x",
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_upgrade() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let addr = upstream().await;
+        let dir = site_fixture();
+        let route = format!("/api=http://{}", addr);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+        let server = Server {
+            listeners: vec![Listener::Tcp(listener)],
+            router: router(test_state(dir.path(), &["--proxy", &route])),
+            tls: None,
+            activity: Arc::new(Activity::default()),
+            shutdown_timeout: std::time::Duration::from_secs(1),
+        };
+        tokio::spawn(server.run(std::future::pending()));
+
+        let mut stream = tokio::net::TcpStream::connect(proxy_addr).await.unwrap();
+        stream
+            .write_all(
+                b"GET /api/upgrade HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: echo\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0u8; 1];
+            stream.read_exact(&mut byte).await.unwrap();
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8(head).unwrap().to_lowercase();
+        assert!(head.starts_with("http/1.1 101"));
+        assert!(head.contains("upgrade: echo"));
+
+        stream.write_all(b"ping").await.unwrap();
+        let mut echoed = [0u8; 4];
+        tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            stream.read_exact(&mut echoed),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(&echoed, b"ping");
+    }
+
+    #[tokio::test]
+    async fn test_directory_archive() {
+        let dir = site_fixture();
+        std::fs::write(dir.path().join("files/.env"), "secret").unwrap();
+
+        let (s, body) = get_in(dir.path(), "files", &[("archive", "zip")]).await;
+        assert_eq!(s.status(), StatusCode::OK);
+        assert_eq!(s.headers()[header::CONTENT_TYPE], "application/zip");
+        assert_eq!(
+            s.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"files.zip\""
+        );
+        let zip = zip::ZipArchive::new(std::io::Cursor::new(body)).unwrap();
+        let mut names: Vec<_> = zip.file_names().collect();
+        names.sort();
+        assert_eq!(names, ["files/", "files/a.txt"]);
+
+        let (s, body) = get_in(
+            dir.path(),
+            "files/",
+            &[("archive", "tar.gz"), ("hidden", "true")],
+        )
+        .await;
+        assert_eq!(s.headers()[header::CONTENT_TYPE], "application/gzip");
+        let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(&body[..]));
+        let names: Vec<_> = tar
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().display().to_string())
+            .collect();
+        assert_eq!(names, ["files", "files/.env", "files/a.txt"]);
+
+        let (s, body) = get_in(dir.path(), "files/", &[("archive", "rar")]).await;
+        assert_eq!(s.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(body, b"Unsupported archive format");
+
+        let (status, _) = request(
+            test_state(dir.path(), &["--no-listing"]),
+            "/files/?archive=zip",
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (_, body) = get_in(dir.path(), "files/", &[]).await;
+        let html = String::from_utf8(body).unwrap();
+        assert!(html.contains("<a href=\"?archive=zip\">zip</a>"));
+    }
+
+    #[tokio::test]
+    async fn test_health_and_metrics() {
+        let dir = site_fixture();
+        let app = router(test_state(dir.path(), &[]));
+        let call = |uri: &str| {
+            let req = axum::http::Request::get(uri).body(Body::empty()).unwrap();
+            let app = app.clone();
+            async move {
+                let s = app.oneshot(req).await.unwrap();
+                let status = s.status();
+                let body = to_bytes(s.into_body(), usize::MAX).await.unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        assert_eq!(call("/healthz").await, (StatusCode::OK, "ok\n".to_string()));
+        assert_eq!(call("/files/a.txt").await.0, StatusCode::OK);
+        assert_eq!(call("/missing.txt").await.0, StatusCode::NOT_FOUND);
+
+        let (status, text) = call("/metrics").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(text.contains("rcli_http_requests_total{status=\"200\"} 2\n"));
+        assert!(text.contains("rcli_http_requests_total{status=\"404\"} 1\n"));
+        // "ok\n", "a" and the 404 message
+        let sent = 3 + 1 + "Not found file".len();
+        assert!(text.contains(&format!("rcli_http_response_bytes_total {}\n", sent)));
+        assert!(text.contains("rcli_http_request_duration_seconds_count 3\n"));
+
+        // with an admin port the names are free for files again
+        let (status, _) = request(
+            test_state(dir.path(), &["--admin-port", "9090"]),
+            "/healthz",
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    fn basic_get(uri: &str, credentials: Option<&str>) -> axum::http::Request<Body> {
+        use base64::Engine;
+        let mut req = axum::http::Request::get(uri);
+        if let Some(credentials) = credentials {
+            let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+            req = req.header(header::AUTHORIZATION, format!("Basic {}", encoded));
+        }
+        req.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_basic_auth() {
+        let dir = site_fixture();
+        let htpasswd = dir.path().join("htpasswd");
+        std::fs::write(
+            &htpasswd,
+            format!("# team\nbob:{}\n", bcrypt::hash("hunter2", 4).unwrap()),
+        )
+        .unwrap();
+        let args = [
+            "--auth",
+            "alice:secret",
+            "--auth-file",
+            htpasswd.to_str().unwrap(),
+            "--auth-realm",
+            "team",
+            "--cors",
+        ];
+        let state = || test_state(dir.path(), &args);
+
+        let (s, _) = send(state(), basic_get("/files/a.txt", None)).await;
+        assert_eq!(s.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            s.headers()[header::WWW_AUTHENTICATE],
+            "Basic realm=\"team\", charset=\"UTF-8\""
+        );
+        let (s, _) = send(state(), basic_get("/files/a.txt", Some("alice:wrong"))).await;
+        assert_eq!(s.status(), StatusCode::UNAUTHORIZED);
+        let (s, _) = send(state(), basic_get("/files/a.txt", Some("bob:secret"))).await;
+        assert_eq!(s.status(), StatusCode::UNAUTHORIZED);
+
+        let (s, body) = send(state(), basic_get("/files/a.txt", Some("alice:secret"))).await;
+        assert_eq!(s.status(), StatusCode::OK);
+        assert_eq!(body, b"a");
+        let (s, body) = send(state(), basic_get("/files/a.txt", Some("bob:hunter2"))).await;
+        assert_eq!(s.status(), StatusCode::OK);
+        assert_eq!(body, b"a");
+
+        // browsers send preflights without credentials
+        let preflight = axum::http::Request::options("/files/a.txt")
+            .header(header::ORIGIN, "https://a.example")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .body(Body::empty())
+            .unwrap();
+        let (s, _) = send(state(), preflight).await;
+        assert_eq!(s.status(), StatusCode::OK);
+
+        std::fs::write(&htpasswd, "carol:plaintext\n").unwrap();
+        assert!(HttpServerState::try_new(serve_opts(
+            dir.path(),
+            &["--auth-file", htpasswd.to_str().unwrap()]
+        ))
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ip_filter() {
+        let dir = site_fixture();
+        let from = |ip: &str| {
+            let mut req = basic_get("/files/a.txt", None);
+            let addr: SocketAddr = format!("{}:40000", ip).parse().unwrap();
+            req.extensions_mut()
+                .insert(axum::extract::ConnectInfo(addr));
+            req
+        };
+        let args = ["--allow", "10.0.0.0/8", "--deny", "10.0.0.13"];
+        let state = || test_state(dir.path(), &args);
+
+        let (s, body) = send(state(), from("10.1.2.3")).await;
+        assert_eq!(s.status(), StatusCode::OK);
+        assert_eq!(body, b"a");
+        let (s, _) = send(state(), from("10.0.0.13")).await;
+        assert_eq!(s.status(), StatusCode::FORBIDDEN);
+        let (s, _) = send(state(), from("192.168.1.1")).await;
+        assert_eq!(s.status(), StatusCode::FORBIDDEN);
+        // IPv4 clients on a dual stack socket
+        let (s, _) = send(state(), from("[::ffff:10.1.2.3]")).await;
+        assert_eq!(s.status(), StatusCode::OK);
+
+        // the filter runs before auth, so denied clients get no challenge
+        let args = ["--allow", "10.0.0.0/8", "--auth", "alice:secret"];
+        let (s, _) = send(test_state(dir.path(), &args), from("192.168.1.1")).await;
+        assert_eq!(s.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_access_log() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "hello").unwrap();
+        let log = dir.path().join("access.log");
+        let log_arg = log.to_str().unwrap();
+        let state = test_state(
+            dir.path(),
+            &["--access-log", log_arg, "--access-log-format", "json"],
+        );
+        let router = router(state);
+        for uri in ["/a.txt", "/missing"] {
+            let req = axum::http::Request::get(uri).body(Body::empty()).unwrap();
+            router.clone().oneshot(req).await.unwrap();
+        }
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["target"], "/a.txt");
+        assert_eq!(lines[0]["status"], 200);
+        assert_eq!(lines[0]["bytes"], 5);
+        assert_eq!(lines[1]["status"], 404);
+    }
+
+    #[tokio::test]
+    async fn test_serve_self_signed_tls() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "hello tls").unwrap();
+        let (cert, key) = tls::self_signed(&["localhost".to_string()]).unwrap();
+        let config = tokio_rustls::rustls::ServerConfig::builder_with_provider(Arc::new(
+            tokio_rustls::rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.clone()], key)
+        .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server {
+            listeners: vec![Listener::Tcp(listener)],
+            router: router(test_state(dir.path(), &[])),
+            tls: Some(acceptor),
+            activity: Arc::new(Activity::default()),
+            shutdown_timeout: std::time::Duration::from_secs(1),
+        };
+        tokio::spawn(server.run(std::future::pending()));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert).unwrap();
+        let config = ClientConfig::builder_with_provider(Arc::new(
+            tokio_rustls::rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut stream = connector
+            .connect("localhost".try_into().unwrap(), stream)
+            .await
+            .unwrap();
+        stream
+            .write_all(b"GET /a.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("hello tls"));
+    }
+}
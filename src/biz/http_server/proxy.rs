@@ -0,0 +1,325 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Response, StatusCode, Version};
+use axum::middleware::Next;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use log::{info, warn};
+
+use crate::{HttpServeOpts, ProxyRoute};
+
+// headers that describe a single connection and must not be forwarded, RFC 9110 §7.6.1
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const X_FORWARDED_PREFIX: HeaderName = HeaderName::from_static("x-forwarded-prefix");
+
+/// The `--proxy` routes and the client used to reach their upstreams.
+pub(super) struct Proxy {
+    // longest prefix first, so the most specific route wins
+    routes: Vec<ProxyRoute>,
+    client: Client<HttpConnector, Body>,
+    timeout: Duration,
+    scheme: &'static str,
+}
+
+impl Proxy {
+    /// Build the proxy for `opts`, or `None` without `--proxy` routes.
+    pub fn new(opts: &HttpServeOpts) -> Option<Self> {
+        if opts.proxy.is_empty() {
+            return None;
+        }
+        let mut routes = opts.proxy.clone();
+        routes.sort_by_key(|route| std::cmp::Reverse(route.prefix.len()));
+        let mut connector = HttpConnector::new();
+        connector.set_connect_timeout(Some(opts.proxy_connect_timeout));
+        let tls = opts.tls_cert.is_some() || opts.tls_self_signed;
+        Some(Self {
+            routes,
+            client: Client::builder(TokioExecutor::new()).build(connector),
+            timeout: opts.proxy_timeout,
+            scheme: if tls { "https" } else { "http" },
+        })
+    }
+
+    fn route(&self, path: &str) -> Option<&ProxyRoute> {
+        self.routes.iter().find(|route| route.matches(path))
+    }
+
+    async fn forward(&self, route: &ProxyRoute, mut req: Request) -> Response<Body> {
+        let path_and_query = req
+            .uri()
+            .path_and_query()
+            .map_or("/", |pq| pq.as_str())
+            .to_string();
+        let target = upstream_uri(route, &path_and_query);
+        info!("Proxying {} {} to {}", req.method(), path_and_query, target);
+
+        let upgrade = upgrade_protocol(req.headers());
+        let client_upgrade = upgrade.is_some().then(|| hyper::upgrade::on(&mut req));
+        let peer = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+
+        let (mut parts, body) = req.into_parts();
+        let host = parts.headers.remove(header::HOST).or_else(|| {
+            // HTTP/2 clients send the host as the uri authority
+            parts
+                .uri
+                .authority()
+                .and_then(|a| HeaderValue::from_str(a.as_str()).ok())
+        });
+        remove_hop_by_hop(&mut parts.headers);
+        if let Some(protocol) = upgrade {
+            parts
+                .headers
+                .insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+            parts.headers.insert(header::UPGRADE, protocol);
+        }
+        set_forwarded(&mut parts.headers, peer, host, self.scheme, &route.prefix);
+        parts.uri = match target.parse() {
+            Ok(uri) => uri,
+            Err(_) => return error_response(StatusCode::BAD_REQUEST, "Invalid proxy path"),
+        };
+        // the client speaks HTTP/1.1 to upstreams whatever the browser used
+        parts.version = Version::HTTP_11;
+
+        let request = self.client.request(Request::from_parts(parts, body));
+        let mut response = match tokio::time::timeout(self.timeout, request).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => {
+                warn!("Proxy to {} failed: {}", target, e);
+                return error_response(StatusCode::BAD_GATEWAY, "Upstream unavailable");
+            }
+            Err(_) => {
+                warn!("Proxy to {} timed out after {:?}", target, self.timeout);
+                return error_response(StatusCode::GATEWAY_TIMEOUT, "Upstream timed out");
+            }
+        };
+
+        if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+            let Some(client_upgrade) = client_upgrade else {
+                return error_response(StatusCode::BAD_GATEWAY, "Unexpected upgrade");
+            };
+            let upstream_upgrade = hyper::upgrade::on(&mut response);
+            tokio::spawn(async move {
+                match tokio::try_join!(client_upgrade, upstream_upgrade) {
+                    Ok((client, upstream)) => {
+                        let mut client = TokioIo::new(client);
+                        let mut upstream = TokioIo::new(upstream);
+                        let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+                    }
+                    Err(e) => warn!("Proxy upgrade failed: {}", e),
+                }
+            });
+            // `Connection` and `Upgrade` are kept, the client needs them
+            return response.map(Body::new);
+        }
+
+        let (mut parts, body) = response.into_parts();
+        remove_hop_by_hop(&mut parts.headers);
+        if let Some(location) = parts
+            .headers
+            .get(header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| rewrite_location(route, v))
+            .and_then(|v| HeaderValue::from_str(&v).ok())
+        {
+            parts.headers.insert(header::LOCATION, location);
+        }
+        Response::from_parts(parts, Body::new(body))
+    }
+}
+
+impl fmt::Debug for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Proxy")
+            .field("routes", &self.routes)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Middleware sending requests under a `--proxy` prefix upstream, everything
+/// else on to the file server.
+pub(super) async fn proxy_middleware(
+    State(proxy): State<Arc<Proxy>>,
+    req: Request,
+    next: Next,
+) -> Response<Body> {
+    match proxy.route(req.uri().path()) {
+        Some(route) => proxy.forward(route, req).await,
+        None => next.run(req).await,
+    }
+}
+
+/// Replace the route prefix of `path_and_query` with the upstream.
+fn upstream_uri(route: &ProxyRoute, path_and_query: &str) -> String {
+    let rest = &path_and_query[route.prefix.len()..];
+    let has_base_path = route.upstream["http://".len()..].contains('/');
+    if rest.starts_with('/') || has_base_path {
+        format!("{}{}", route.upstream, rest)
+    } else {
+        format!("{}/{}", route.upstream, rest)
+    }
+}
+
+/// Map redirects to the upstream back under the route prefix.
+fn rewrite_location(route: &ProxyRoute, location: &str) -> Option<String> {
+    let rest = location.strip_prefix(&route.upstream)?;
+    if !(rest.is_empty() || rest.starts_with(['/', '?', '#'])) {
+        return None;
+    }
+    let prefix = if route.prefix.is_empty() && !rest.starts_with('/') {
+        "/"
+    } else {
+        &route.prefix
+    };
+    Some(format!("{}{}", prefix, rest))
+}
+
+/// The protocol a request asks to switch to, e.g. `websocket`.
+fn upgrade_protocol(headers: &HeaderMap) -> Option<HeaderValue> {
+    let wants_upgrade = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+    wants_upgrade
+        .then(|| headers.get(header::UPGRADE).cloned())
+        .flatten()
+}
+
+fn remove_hop_by_hop(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP {
+        headers.remove(*name);
+    }
+}
+
+fn set_forwarded(
+    headers: &mut HeaderMap,
+    peer: Option<std::net::IpAddr>,
+    host: Option<HeaderValue>,
+    scheme: &'static str,
+    prefix: &str,
+) {
+    if let Some(peer) = peer {
+        let forwarded_for = match headers.get(&X_FORWARDED_FOR).and_then(|v| v.to_str().ok()) {
+            Some(previous) => format!("{}, {}", previous, peer),
+            None => peer.to_string(),
+        };
+        if let Ok(value) = HeaderValue::from_str(&forwarded_for) {
+            headers.insert(X_FORWARDED_FOR, value);
+        }
+    }
+    if let Some(host) = host {
+        headers.insert(X_FORWARDED_HOST, host);
+    }
+    headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static(scheme));
+    if let Ok(prefix) = HeaderValue::from_str(prefix) {
+        if !prefix.is_empty() {
+            headers.insert(X_FORWARDED_PREFIX, prefix);
+        }
+    }
+}
+
+fn error_response(status: StatusCode, message: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(message))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(s: &str) -> ProxyRoute {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_upstream_uri() {
+        let api = route("/api=http://up:3000");
+        assert_eq!(
+            upstream_uri(&api, "/api/users?x=1"),
+            "http://up:3000/users?x=1"
+        );
+        assert_eq!(upstream_uri(&api, "/api"), "http://up:3000/");
+        assert_eq!(upstream_uri(&api, "/api?x=1"), "http://up:3000/?x=1");
+
+        let based = route("/api=http://up:3000/v1");
+        assert_eq!(
+            upstream_uri(&based, "/api/users"),
+            "http://up:3000/v1/users"
+        );
+        assert_eq!(upstream_uri(&based, "/api"), "http://up:3000/v1");
+
+        let root = route("/=http://up:3000");
+        assert_eq!(upstream_uri(&root, "/a%20b"), "http://up:3000/a%20b");
+    }
+
+    #[test]
+    fn test_rewrite_location() {
+        let api = route("/api=http://up:3000");
+        assert_eq!(
+            rewrite_location(&api, "http://up:3000/login?next=1"),
+            Some("/api/login?next=1".to_string())
+        );
+        assert_eq!(rewrite_location(&api, "http://up:30001/x"), None);
+        assert_eq!(rewrite_location(&api, "/relative"), None);
+        let root = route("/=http://up:3000");
+        assert_eq!(
+            rewrite_location(&root, "http://up:3000"),
+            Some("/".to_string())
+        );
+    }
+
+    #[test]
+    fn test_remove_hop_by_hop() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONNECTION, "keep-alive, x-secret".parse().unwrap());
+        headers.insert("x-secret", "1".parse().unwrap());
+        headers.insert(header::TRANSFER_ENCODING, "chunked".parse().unwrap());
+        headers.insert(header::ACCEPT, "*/*".parse().unwrap());
+        assert!(upgrade_protocol(&headers).is_none());
+        remove_hop_by_hop(&mut headers);
+        assert_eq!(headers.len(), 1);
+        assert!(headers.contains_key(header::ACCEPT));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONNECTION, "Upgrade".parse().unwrap());
+        headers.insert(header::UPGRADE, "websocket".parse().unwrap());
+        assert_eq!(upgrade_protocol(&headers).unwrap(), "websocket");
+    }
+}
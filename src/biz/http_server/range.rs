@@ -0,0 +1,127 @@
+// more ranges than this in a single request are ignored and the full body is sent
+const MAX_RANGES: usize = 32;
+
+/// Outcome of interpreting a `Range` header against a file of known length.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum RangeRequest {
+    /// No usable `Range` header, serve the whole file.
+    Full,
+    /// Satisfiable ranges as inclusive `(start, end)` byte offsets.
+    Partial(Vec<(u64, u64)>),
+    /// None of the requested ranges overlap the file.
+    Unsatisfiable,
+}
+
+/// Parse a `bytes=` range header (RFC 9110 §14.1.2).
+///
+/// Syntactically invalid headers are ignored rather than rejected, as the RFC
+/// allows, so the client simply receives the whole file.
+pub(super) fn parse_range(value: &str, len: u64) -> RangeRequest {
+    let Some((unit, specs)) = value.trim().split_once('=') else {
+        return RangeRequest::Full;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return RangeRequest::Full;
+    }
+
+    let specs: Vec<&str> = specs
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return RangeRequest::Full;
+    }
+
+    let mut ranges = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let Some((start, end)) = spec.split_once('-') else {
+            return RangeRequest::Full;
+        };
+        let (start, end) = (start.trim(), end.trim());
+        let range = if start.is_empty() {
+            // suffix range: the last N bytes
+            let Ok(suffix) = end.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            (suffix > 0 && len > 0).then(|| (len.saturating_sub(suffix), len - 1))
+        } else {
+            let Ok(start) = start.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let end = if end.is_empty() {
+                u64::MAX
+            } else {
+                match end.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => return RangeRequest::Full,
+                }
+            };
+            (start < len).then(|| (start, end.min(len - 1)))
+        };
+        ranges.extend(range);
+    }
+
+    if ranges.is_empty() {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(ranges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_single_range() {
+        assert_eq!(
+            parse_range("bytes=0-100", 1000),
+            RangeRequest::Partial(vec![(0, 100)])
+        );
+        assert_eq!(
+            parse_range("bytes=900-", 1000),
+            RangeRequest::Partial(vec![(900, 999)])
+        );
+        assert_eq!(
+            parse_range("bytes=-100", 1000),
+            RangeRequest::Partial(vec![(900, 999)])
+        );
+        assert_eq!(
+            parse_range("bytes=0-5000", 1000),
+            RangeRequest::Partial(vec![(0, 999)])
+        );
+        assert_eq!(
+            parse_range("bytes=-5000", 1000),
+            RangeRequest::Partial(vec![(0, 999)])
+        );
+    }
+
+    #[test]
+    fn test_parse_multi_range() {
+        assert_eq!(
+            parse_range("bytes=0-9, 20-29,2000-3000", 1000),
+            RangeRequest::Partial(vec![(0, 9), (20, 29)])
+        );
+    }
+
+    #[test]
+    fn test_parse_unsatisfiable() {
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert_eq!(parse_range("items=0-1", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=5-1", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=a-b", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=", 1000), RangeRequest::Full);
+        let many = format!("bytes={}", vec!["0-0"; MAX_RANGES + 1].join(","));
+        assert_eq!(parse_range(&many, 1000), RangeRequest::Full);
+    }
+}
@@ -0,0 +1,211 @@
+use std::path::{Component, Path, PathBuf};
+
+use axum::http::StatusCode;
+
+/// Map a decoded request path onto the served directory.
+///
+/// `root` must already be canonical. The request path is normalised
+/// lexically first, so `..` can never climb above `root`, and absolute or
+/// drive-prefixed segments are rejected. Symbolic links are refused unless
+/// `follow_symlinks` is set, in which case they are resolved wherever they
+/// point.
+pub(super) fn resolve_path(
+    root: &Path,
+    request: &str,
+    follow_symlinks: bool,
+) -> Result<PathBuf, StatusCode> {
+    let segments = normalize(request).ok_or(StatusCode::FORBIDDEN)?;
+
+    if follow_symlinks {
+        let full_path = segments.iter().fold(root.to_path_buf(), |p, s| p.join(s));
+        return full_path.canonicalize().map_err(|_| StatusCode::NOT_FOUND);
+    }
+
+    let mut full_path = root.to_path_buf();
+    for segment in segments {
+        full_path.push(segment);
+        let metadata = full_path
+            .symlink_metadata()
+            .map_err(|_| StatusCode::NOT_FOUND)?;
+        if metadata.file_type().is_symlink() {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+    // no symlinks were crossed, so this only fails on races with the filesystem
+    match full_path.canonicalize() {
+        Ok(canonical) if canonical.starts_with(root) => Ok(canonical),
+        Ok(_) => Err(StatusCode::FORBIDDEN),
+        Err(_) => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Map a request path onto a file that may not exist yet, for uploads.
+///
+/// The parent directory is resolved like [`resolve_path`]; the last segment
+/// must be a plain file name and must not name a directory or, unless
+/// `follow_symlinks` is set, a symbolic link.
+pub(super) fn resolve_upload_target(
+    root: &Path,
+    request: &str,
+    follow_symlinks: bool,
+) -> Result<PathBuf, StatusCode> {
+    let mut segments = normalize(request).ok_or(StatusCode::FORBIDDEN)?;
+    let name = segments.pop().ok_or(StatusCode::BAD_REQUEST)?;
+    let parent = resolve_path(root, &segments.join("/"), follow_symlinks)?;
+    if !parent.is_dir() {
+        return Err(StatusCode::CONFLICT);
+    }
+    upload_target_in(&parent, name, follow_symlinks)
+}
+
+/// Join an uploaded file name onto an already resolved directory.
+///
+/// Client supplied names are reduced to their last component, so a browser
+/// sending `C:\Users\me\a.txt` uploads `a.txt`.
+pub(super) fn upload_target_in(
+    dir: &Path,
+    name: &str,
+    follow_symlinks: bool,
+) -> Result<PathBuf, StatusCode> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    match normalize(name).as_deref() {
+        Some([segment]) if *segment == name => {}
+        _ => return Err(StatusCode::BAD_REQUEST),
+    }
+    let target = dir.join(name);
+    match target.symlink_metadata() {
+        Ok(metadata) if metadata.file_type().is_symlink() && !follow_symlinks => {
+            Err(StatusCode::FORBIDDEN)
+        }
+        Ok(_) if target.is_dir() => Err(StatusCode::CONFLICT),
+        _ => Ok(target),
+    }
+}
+
+/// Split a request path into plain segments, resolving `.` and `..`.
+///
+/// Returns `None` when the path would escape the root or contains a segment
+/// that is not a plain file name.
+fn normalize(request: &str) -> Option<Vec<&str>> {
+    let mut segments = Vec::new();
+    for segment in request.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            _ => {
+                if segment.contains(['\\', '\0']) {
+                    return None;
+                }
+                let mut components = Path::new(segment).components();
+                match (components.next(), components.next()) {
+                    (Some(Component::Normal(_)), None) => segments.push(segment),
+                    _ => return None,
+                }
+            }
+        }
+    }
+    Some(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("a/b/c"), Some(vec!["a", "b", "c"]));
+        assert_eq!(normalize("/a//./b/"), Some(vec!["a", "b"]));
+        assert_eq!(normalize("a/../b"), Some(vec!["b"]));
+        assert_eq!(normalize(""), Some(vec![]));
+        assert_eq!(normalize("/"), Some(vec![]));
+        assert_eq!(normalize(".."), None);
+        assert_eq!(normalize("a/../../b"), None);
+        assert_eq!(normalize("../../etc/passwd"), None);
+        assert_eq!(normalize("a\\..\\..\\b"), None);
+        assert_eq!(normalize("a\0b"), None);
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("sub")).unwrap();
+        std::fs::write(root.join("sub/a.txt"), "a").unwrap();
+
+        assert_eq!(
+            resolve_path(&root, "sub/a.txt", false),
+            Ok(root.join("sub/a.txt"))
+        );
+        assert_eq!(resolve_path(&root, "/", false), Ok(root.clone()));
+        assert_eq!(
+            resolve_path(&root, "sub/missing", false),
+            Err(StatusCode::NOT_FOUND)
+        );
+        assert_eq!(
+            resolve_path(&root, "../../etc/passwd", false),
+            Err(StatusCode::FORBIDDEN)
+        );
+        // an absolute request path stays inside the root
+        assert_eq!(
+            resolve_path(&root, "/etc/passwd", false),
+            Err(StatusCode::NOT_FOUND)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_symlink() {
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.join("link")).unwrap();
+
+        assert_eq!(
+            resolve_path(&root, "link/secret.txt", false),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            resolve_path(&root, "link/secret.txt", true),
+            Ok(outside.path().canonicalize().unwrap().join("secret.txt"))
+        );
+    }
+
+    #[test]
+    fn test_resolve_upload_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("sub")).unwrap();
+
+        assert_eq!(
+            resolve_upload_target(&root, "sub/new.txt", false),
+            Ok(root.join("sub/new.txt"))
+        );
+        assert_eq!(
+            resolve_upload_target(&root, "missing/new.txt", false),
+            Err(StatusCode::NOT_FOUND)
+        );
+        assert_eq!(
+            resolve_upload_target(&root, "sub", false),
+            Err(StatusCode::CONFLICT)
+        );
+        assert_eq!(
+            resolve_upload_target(&root, "../new.txt", false),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            resolve_upload_target(&root, "/", false),
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            upload_target_in(&root, "C:\\Users\\me\\a.txt", false),
+            Ok(root.join("a.txt"))
+        );
+        assert_eq!(
+            upload_target_in(&root, "..", false),
+            Err(StatusCode::BAD_REQUEST)
+        );
+    }
+}
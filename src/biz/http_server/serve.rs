@@ -0,0 +1,629 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context as _, Result};
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::ConnectInfo;
+use axum::Router;
+use hyper::body::{Frame, Incoming, SizeHint};
+use hyper::service::service_fn;
+use hyper::Request;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use log::{info, warn};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::sync::watch;
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+
+use crate::BindAddr;
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+/// A bound socket the server accepts connections on.
+#[derive(Debug)]
+pub(super) enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+/// Tracks open connections, requests in flight and the time of the last
+/// request, for `--idle-exit` and the connection gauge in `/metrics`.
+#[derive(Debug)]
+pub(super) struct Activity {
+    started: Instant,
+    // milliseconds since `started`
+    last_active: AtomicU64,
+    connections: AtomicUsize,
+    // until the response body is sent, so downloads keep the server busy
+    requests: AtomicUsize,
+}
+
+/// Serves a router on a set of listeners until a shutdown future resolves.
+pub(super) struct Server {
+    pub listeners: Vec<Listener>,
+    pub router: Router,
+    pub tls: Option<TlsAcceptor>,
+    pub activity: Arc<Activity>,
+    /// How long open connections may take to finish once shutdown starts.
+    pub shutdown_timeout: Duration,
+}
+
+// a client that connects but never finishes the handshake holds a task
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct ConnectionGuard(Arc<Activity>);
+
+impl Listener {
+    /// Bind `addr`, using `default_port` when it does not name a port.
+    ///
+    /// A hostname is bound on every address it resolves to; addresses that
+    /// cannot be bound are skipped as long as at least one succeeds.
+    pub async fn bind(addr: &BindAddr, default_port: u16) -> Result<Vec<Listener>> {
+        match addr {
+            #[cfg(unix)]
+            BindAddr::Unix(path) => {
+                // a socket left behind by a previous run would make bind fail,
+                // but one that still accepts connections belongs to a live server
+                if path.exists() && std::os::unix::net::UnixStream::connect(path).is_err() {
+                    std::fs::remove_file(path)?;
+                }
+                let listener =
+                    UnixListener::bind(path).with_context(|| format!("Failed to bind {}", addr))?;
+                Ok(vec![Listener::Unix(listener, path.clone())])
+            }
+            #[cfg(not(unix))]
+            BindAddr::Unix(_) => Err(anyhow!(
+                "Unix domain sockets are not supported on this platform"
+            )),
+            BindAddr::Tcp { host, port } => {
+                let port = port.unwrap_or(default_port);
+                let mut addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+                    .await
+                    .with_context(|| format!("Failed to resolve {}", host))?
+                    .collect();
+                addrs.dedup();
+
+                let mut listeners = Vec::with_capacity(addrs.len());
+                let mut last_error = None;
+                for addr in addrs {
+                    match TcpListener::bind(addr).await {
+                        Ok(listener) => listeners.push(Listener::Tcp(listener)),
+                        Err(e) => {
+                            warn!("Failed to bind {}: {}", addr, e);
+                            last_error = Some(e);
+                        }
+                    }
+                }
+                if listeners.is_empty() {
+                    return Err(match last_error {
+                        Some(e) => anyhow!("Failed to bind {}: {}", addr, e),
+                        None => anyhow!("{} did not resolve to any address", host),
+                    });
+                }
+                Ok(listeners)
+            }
+        }
+    }
+
+    /// The bound address, in `--bind` syntax.
+    pub fn local_addr(&self) -> String {
+        match self {
+            Listener::Tcp(listener) => listener
+                .local_addr()
+                .map(|addr| addr.to_string())
+                .unwrap_or_default(),
+            #[cfg(unix)]
+            Listener::Unix(_, path) => format!("unix:{}", path.display()),
+        }
+    }
+
+    async fn accept(&self) -> std::io::Result<(Box<dyn Io>, Option<SocketAddr>)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, peer) = listener.accept().await?;
+                Ok((Box::new(stream), Some(peer)))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => {
+                let (stream, _) = listener.accept().await?;
+                Ok((Box::new(stream), None))
+            }
+        }
+    }
+
+    fn close(self) {
+        #[cfg(unix)]
+        if let Listener::Unix(listener, path) = self {
+            drop(listener);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            last_active: AtomicU64::new(0),
+            connections: AtomicUsize::new(0),
+            requests: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Activity {
+    fn touch(&self) {
+        let now = self.started.elapsed().as_millis() as u64;
+        self.last_active.store(now, Ordering::Relaxed);
+    }
+
+    /// The number of open connections.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    /// Resolve once no request is in flight and nothing has happened for
+    /// `after`; idle keep-alive connections do not count.
+    pub async fn idle(&self, after: Duration) {
+        let period = (after / 4).clamp(Duration::from_millis(10), Duration::from_secs(1));
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let last = Duration::from_millis(self.last_active.load(Ordering::Relaxed));
+            if self.requests.load(Ordering::Relaxed) == 0
+                && self.started.elapsed().saturating_sub(last) >= after
+            {
+                return;
+            }
+        }
+    }
+}
+
+impl ConnectionGuard {
+    fn new(activity: Arc<Activity>) -> Self {
+        activity.connections.fetch_add(1, Ordering::Relaxed);
+        activity.touch();
+        Self(activity)
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.connections.fetch_sub(1, Ordering::Relaxed);
+        self.0.touch();
+    }
+}
+
+struct RequestGuard(Arc<Activity>);
+
+impl RequestGuard {
+    fn new(activity: Arc<Activity>) -> Self {
+        activity.requests.fetch_add(1, Ordering::Relaxed);
+        activity.touch();
+        Self(activity)
+    }
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        self.0.requests.fetch_sub(1, Ordering::Relaxed);
+        self.0.touch();
+    }
+}
+
+/// A response body holding its request in flight until it is sent or dropped.
+struct TrackedBody {
+    body: Body,
+    _guard: RequestGuard,
+}
+
+impl HttpBody for TrackedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        Pin::new(&mut self.body).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+impl Server {
+    /// Accept connections until `shutdown` resolves, then stop accepting and
+    /// give open connections up to `shutdown_timeout` to finish.
+    ///
+    /// The peer address of TCP connections is exposed to handlers as
+    /// `ConnectInfo<SocketAddr>`.
+    pub async fn run(self, shutdown: impl Future<Output = ()>) -> Result<()> {
+        // `signal` tells everyone to stop; `close` is held by every task so
+        // `close_tx.closed()` resolves once they have all finished
+        let (signal_tx, signal_rx) = watch::channel(());
+        let (close_tx, close_rx) = watch::channel(());
+        for listener in self.listeners {
+            tokio::spawn(accept_loop(
+                listener,
+                self.router.clone(),
+                self.tls.clone(),
+                self.activity.clone(),
+                signal_rx.clone(),
+                close_rx.clone(),
+            ));
+        }
+        drop(signal_rx);
+        drop(close_rx);
+
+        shutdown.await;
+        info!("Shutting down, waiting for open connections to finish");
+        let _ = signal_tx.send(());
+        if tokio::time::timeout(self.shutdown_timeout, close_tx.closed())
+            .await
+            .is_err()
+        {
+            warn!(
+                "{} connection(s) still open after {:?}, exiting anyway",
+                close_tx.receiver_count(),
+                self.shutdown_timeout
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Resolve on SIGINT (ctrl-c) or, on unix, SIGTERM.
+pub(super) async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for ctrl-c: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+async fn accept_loop(
+    listener: Listener,
+    router: Router,
+    tls: Option<TlsAcceptor>,
+    activity: Arc<Activity>,
+    mut signal: watch::Receiver<()>,
+    close: watch::Receiver<()>,
+) {
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = signal.changed() => break,
+        };
+        let (stream, peer) = match accepted {
+            Ok(conn) => conn,
+            Err(e) => {
+                // usually running out of file descriptors, back off for a moment
+                warn!("Failed to accept connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        tokio::spawn(serve_connection(
+            stream,
+            peer,
+            router.clone(),
+            tls.clone(),
+            ConnectionGuard::new(activity.clone()),
+            signal.clone(),
+            close.clone(),
+        ));
+    }
+    listener.close();
+}
+
+async fn serve_connection(
+    stream: Box<dyn Io>,
+    peer: Option<SocketAddr>,
+    router: Router,
+    tls: Option<TlsAcceptor>,
+    guard: ConnectionGuard,
+    mut signal: watch::Receiver<()>,
+    _close: watch::Receiver<()>,
+) {
+    let stream: Box<dyn Io> = match tls {
+        Some(acceptor) => match tls_handshake(&acceptor, stream, peer, TLS_HANDSHAKE_TIMEOUT).await
+        {
+            Some(stream) => stream,
+            None => return,
+        },
+        None => stream,
+    };
+
+    let activity = guard.0.clone();
+    let service = service_fn(move |mut req: Request<Incoming>| {
+        let guard = RequestGuard::new(activity.clone());
+        if let Some(peer) = peer {
+            req.extensions_mut().insert(ConnectInfo(peer));
+        }
+        let response = router.clone().oneshot(req);
+        async move {
+            let response = response.await?;
+            Ok::<_, Infallible>(response.map(|body| {
+                Body::new(TrackedBody {
+                    body,
+                    _guard: guard,
+                })
+            }))
+        }
+    });
+    let builder = Builder::new(TokioExecutor::new());
+    let conn = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+    tokio::pin!(conn);
+
+    let mut draining = false;
+    loop {
+        tokio::select! {
+            result = conn.as_mut() => {
+                if let Err(e) = result {
+                    warn!("Error serving connection from {:?}: {}", peer, e);
+                }
+                break;
+            }
+            _ = signal.changed(), if !draining => {
+                // finish the in-flight request, then close the connection
+                draining = true;
+                conn.as_mut().graceful_shutdown();
+            }
+        }
+    }
+}
+
+/// Complete the TLS handshake, giving up on clients that stall for `timeout`.
+async fn tls_handshake(
+    acceptor: &TlsAcceptor,
+    stream: Box<dyn Io>,
+    peer: Option<SocketAddr>,
+    timeout: Duration,
+) -> Option<Box<dyn Io>> {
+    match tokio::time::timeout(timeout, acceptor.accept(stream)).await {
+        Ok(Ok(stream)) => Some(Box::new(stream)),
+        Ok(Err(e)) => {
+            warn!("TLS handshake with {:?} failed: {}", peer, e);
+            None
+        }
+        Err(_) => {
+            warn!(
+                "TLS handshake with {:?} timed out after {:?}",
+                peer, timeout
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::get;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::oneshot;
+
+    use super::*;
+
+    fn slow_router() -> Router {
+        Router::new().route("/", get(|| async { "ok" })).route(
+            "/slow/:ms",
+            get(
+                |axum::extract::Path(ms): axum::extract::Path<u64>| async move {
+                    tokio::time::sleep(Duration::from_millis(ms)).await;
+                    "slow"
+                },
+            ),
+        )
+    }
+
+    async fn raw_get(mut stream: impl Io, path: &str) -> String {
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            path
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        String::from_utf8(response).unwrap()
+    }
+
+    async fn start(
+        listeners: Vec<Listener>,
+        shutdown_timeout: Duration,
+    ) -> (oneshot::Sender<()>, tokio::task::JoinHandle<Result<()>>) {
+        let (tx, rx) = oneshot::channel::<()>();
+        let server = Server {
+            listeners,
+            router: slow_router(),
+            tls: None,
+            activity: Arc::new(Activity::default()),
+            shutdown_timeout,
+        };
+        let handle = tokio::spawn(server.run(async {
+            let _ = rx.await;
+        }));
+        (tx, handle)
+    }
+
+    fn tcp(host: &str, port: u16) -> BindAddr {
+        BindAddr::Tcp {
+            host: host.to_string(),
+            port: Some(port),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bind_hostname() {
+        let listeners = Listener::bind(&tcp("localhost", 0), 8080).await.unwrap();
+        assert!(!listeners.is_empty());
+        let listeners = Listener::bind(&tcp("::1", 0), 8080).await;
+        if let Ok(listeners) = listeners {
+            assert!(listeners[0].local_addr().starts_with("[::1]:"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_graceful_shutdown_drains_requests() {
+        let listeners = Listener::bind(&tcp("127.0.0.1", 0), 0).await.unwrap();
+        let addr = listeners[0].local_addr();
+        let (shutdown, server) = start(listeners, Duration::from_secs(5)).await;
+
+        let stream = tokio::net::TcpStream::connect(&addr).await.unwrap();
+        let request = tokio::spawn(raw_get(stream, "/slow/300"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown.send(()).unwrap();
+
+        let response = request.await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("slow"));
+        server.await.unwrap().unwrap();
+        // the listener is gone after shutdown
+        assert!(tokio::net::TcpStream::connect(&addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_timeout() {
+        let listeners = Listener::bind(&tcp("127.0.0.1", 0), 0).await.unwrap();
+        let addr = listeners[0].local_addr();
+        let (shutdown, server) = start(listeners, Duration::from_millis(100)).await;
+
+        let stream = tokio::net::TcpStream::connect(&addr).await.unwrap();
+        tokio::spawn(raw_get(stream, "/slow/60000"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("server should give up after the shutdown timeout")
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_idle_exit() {
+        let activity = Arc::new(Activity::default());
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            activity.idle(Duration::from_millis(50)),
+        )
+        .await
+        .unwrap();
+
+        // an idle keep-alive connection does not keep the server busy
+        let connection = ConnectionGuard::new(activity.clone());
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            activity.idle(Duration::from_millis(50)),
+        )
+        .await
+        .unwrap();
+
+        // a request in flight, body included, does
+        let body = TrackedBody {
+            body: Body::from("ok"),
+            _guard: RequestGuard::new(activity.clone()),
+        };
+        let idle = tokio::time::timeout(
+            Duration::from_millis(200),
+            activity.idle(Duration::from_millis(50)),
+        )
+        .await;
+        assert!(idle.is_err());
+        assert_eq!(activity.connections(), 1);
+        drop(body);
+        drop(connection);
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            activity.idle(Duration::from_millis(50)),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rcli.sock");
+        let listeners = Listener::bind(&BindAddr::Unix(path.clone()), 0)
+            .await
+            .unwrap();
+        let (shutdown, server) = start(listeners, Duration::from_secs(5)).await;
+
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let response = raw_get(stream, "/").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("ok"));
+
+        // binding again while the server is alive fails
+        assert!(Listener::bind(&BindAddr::Unix(path.clone()), 0)
+            .await
+            .is_err());
+
+        shutdown.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_tls_handshake_timeout() {
+        let (cert, key) = super::super::tls::self_signed(&["localhost".to_string()]).unwrap();
+        let config = tokio_rustls::rustls::ServerConfig::builder_with_provider(Arc::new(
+            tokio_rustls::rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key)
+        .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        // the client end stays open but never says hello
+        let (_client, server) = tokio::io::duplex(1024);
+        let started = Instant::now();
+        let stream = tls_handshake(&acceptor, Box::new(server), None, Duration::from_millis(50));
+        assert!(stream.await.is_none());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use colored::Colorize;
+use rcgen::{CertificateParams, DnType, KeyPair};
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+use crate::HttpServeOpts;
+
+/// Build the TLS acceptor requested by `opts`, or `None` for plain HTTP.
+///
+/// With `--tls-self-signed` a fresh certificate is generated on every start
+/// and its SHA-256 fingerprint is printed so clients can pin it.
+pub(super) fn tls_acceptor(opts: &HttpServeOpts) -> Result<Option<TlsAcceptor>> {
+    let (certs, key) = if opts.tls_self_signed {
+        let (cert, key) = self_signed(&opts.tls_san)?;
+        println!(
+            "{} {}",
+            "TLS certificate SHA-256 fingerprint:".blue(),
+            fingerprint(&cert)
+        );
+        (vec![cert], key)
+    } else {
+        match (&opts.tls_cert, &opts.tls_key) {
+            (Some(cert), Some(key)) => (load_certs(cert)?, load_key(key)?),
+            _ => return Ok(None),
+        }
+    };
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Some(TlsAcceptor::from(Arc::new(config))))
+}
+
+/// Generate a self-signed certificate valid for `sans`.
+///
+/// IP addresses among `sans` become IP SANs, everything else a DNS name.
+pub(super) fn self_signed(
+    sans: &[String],
+) -> Result<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
+    let key_pair = KeyPair::generate()?;
+    let mut params = CertificateParams::new(sans.to_vec())?;
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "rcli self-signed certificate");
+    let cert = params.self_signed(&key_pair)?;
+    let key = PrivatePkcs8KeyDer::from(key_pair.serialize_der());
+    Ok((cert.der().clone(), PrivateKeyDer::Pkcs8(key)))
+}
+
+/// Colon separated SHA-256 digest of a DER certificate.
+pub(super) fn fingerprint(cert: &CertificateDer) -> String {
+    ring::digest::digest(&ring::digest::SHA256, cert.as_ref())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(File::open(path).with_context(|| path.to_string())?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(anyhow!("No certificate found in {}", path));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(File::open(path).with_context(|| path.to_string())?);
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| anyhow!("No private key found in {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_signed() {
+        let sans = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+        let (cert, _) = self_signed(&sans).unwrap();
+        let fingerprint = fingerprint(&cert);
+        assert_eq!(fingerprint.len(), 32 * 3 - 1);
+        assert!(fingerprint
+            .split(':')
+            .all(|b| b.len() == 2 && u8::from_str_radix(b, 16).is_ok()));
+    }
+
+    #[test]
+    fn test_load_pem() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_pair = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .self_signed(&key_pair)
+            .unwrap();
+        let cert_path = dir.path().join("cert.pem");
+        let key_path = dir.path().join("key.pem");
+        std::fs::write(&cert_path, cert.pem()).unwrap();
+        std::fs::write(&key_path, key_pair.serialize_pem()).unwrap();
+
+        let certs = load_certs(cert_path.to_str().unwrap()).unwrap();
+        assert_eq!(certs, vec![cert.der().clone()]);
+        assert!(load_key(key_path.to_str().unwrap()).is_ok());
+        assert!(load_certs(key_path.to_str().unwrap()).is_err());
+        assert!(load_key(cert_path.to_str().unwrap()).is_err());
+    }
+}
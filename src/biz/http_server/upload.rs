@@ -0,0 +1,293 @@
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::Arc;
+
+use axum::body::{Body, Bytes};
+use axum::extract::{Multipart, Path, State};
+use axum::http::{header, HeaderMap, Response, StatusCode, Uri};
+use futures_util::{pin_mut, Stream, StreamExt};
+use log::{info, warn};
+use percent_encoding::utf8_percent_encode;
+use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
+
+use super::listing::PATH_SEGMENT;
+use super::resolve::{resolve_path, resolve_upload_target, upload_target_in};
+use super::HttpServerState;
+use crate::OverwritePolicy;
+
+// gives up on the rename policy after this many taken names
+const MAX_RENAME_ATTEMPTS: usize = 1000;
+
+type UploadError = (StatusCode, String);
+
+/// `PUT /path`: store the raw request body at `path`.
+///
+/// Answers `201 Created` for new files and `204 No Content` when an existing
+/// file was replaced, with `Location` pointing at the stored file.
+pub(super) async fn put_handler(
+    State(state): State<Arc<HttpServerState>>,
+    Path(path): Path<String>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response<Body>, Infallible> {
+    let result = async {
+        check_content_length(&state, &headers)?;
+        let target = resolve_upload_target(&state.path, &path, state.opts.follow_symlinks)
+            .map_err(|status| (status, status.to_string()))?;
+        let existed = target.exists();
+        let saved = write_upload(&state, &target, body.into_data_stream(), &mut 0).await?;
+        Ok::<_, UploadError>((existed && saved == target, saved))
+    }
+    .await;
+
+    match result {
+        Ok((replaced, saved)) => {
+            let name = file_name(&saved);
+            let parent = uri.path().rsplit_once('/').map_or("", |(p, _)| p);
+            let location = format!("{}/{}", parent, encode_segment(&name));
+            let status = if replaced {
+                StatusCode::NO_CONTENT
+            } else {
+                StatusCode::CREATED
+            };
+            Ok(Response::builder()
+                .status(status)
+                .header(header::LOCATION, location)
+                .body(Body::empty())
+                .unwrap())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+/// `POST /dir/` with `multipart/form-data`: store every file field in `dir`.
+///
+/// Browsers submitting the listing's upload form are redirected back to the
+/// listing, other clients get the stored names as JSON.
+pub(super) async fn form_handler(
+    State(state): State<Arc<HttpServerState>>,
+    path: Option<Path<String>>,
+    uri: Uri,
+    headers: HeaderMap,
+    multipart: Multipart,
+) -> Result<Response<Body>, Infallible> {
+    let path = path.map(|Path(path)| path).unwrap_or_default();
+    match save_form(&state, &path, multipart).await {
+        Ok(files) => {
+            let browser = headers
+                .get(header::ACCEPT)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.contains("text/html"));
+            if browser {
+                return Ok(Response::builder()
+                    .status(StatusCode::SEE_OTHER)
+                    .header(header::LOCATION, uri.path())
+                    .body(Body::empty())
+                    .unwrap());
+            }
+            let body = serde_json::json!({ "files": files }).to_string();
+            Ok(Response::builder()
+                .status(StatusCode::CREATED)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+async fn save_form(
+    state: &HttpServerState,
+    path: &str,
+    mut multipart: Multipart,
+) -> Result<Vec<String>, UploadError> {
+    let dir = resolve_path(&state.path, path, state.opts.follow_symlinks)
+        .map_err(|status| (status, status.to_string()))?;
+    if !dir.is_dir() {
+        return Err((
+            StatusCode::CONFLICT,
+            "Uploads must be posted to a directory".to_string(),
+        ));
+    }
+
+    let mut files = Vec::new();
+    let mut received = 0;
+    while let Some(field) = multipart.next_field().await.map_err(bad_request)? {
+        // plain form fields carry no file name and are ignored
+        let Some(name) = field.file_name().map(str::to_string) else {
+            continue;
+        };
+        if name.is_empty() {
+            continue;
+        }
+        let target = upload_target_in(&dir, &name, state.opts.follow_symlinks)
+            .map_err(|status| (status, format!("Invalid file name {}", name)))?;
+        let saved = write_upload(state, &target, field, &mut received).await?;
+        files.push(file_name(&saved));
+    }
+    Ok(files)
+}
+
+/// Stream `body` into a temporary file next to `target`, then move it into
+/// place according to the overwrite policy.
+///
+/// `received` counts the bytes of every file in the request, so all parts of a
+/// multipart form together stay within `--max-upload-size`.
+///
+/// Returns the path the file was finally stored at, which differs from
+/// `target` under [`OverwritePolicy::Rename`].
+async fn write_upload<S, E>(
+    state: &HttpServerState,
+    target: &FsPath,
+    body: S,
+    received: &mut u64,
+) -> Result<PathBuf, UploadError>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: Display,
+{
+    if state.opts.overwrite == OverwritePolicy::Deny && target.exists() {
+        return Err(conflict(target));
+    }
+    let dir = target.parent().unwrap_or(FsPath::new("."));
+    // the temporary file is removed on drop, so early returns clean up
+    let tmp = tempfile::Builder::new()
+        .prefix(".upload-")
+        .tempfile_in(dir)
+        .map_err(internal)?;
+    let mut file = tokio::fs::File::from_std(tmp.as_file().try_clone().map_err(internal)?);
+
+    let mut written = 0u64;
+    pin_mut!(body);
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(bad_request)?;
+        written += chunk.len() as u64;
+        *received += chunk.len() as u64;
+        if *received > state.opts.max_upload_size {
+            return Err(too_large(state));
+        }
+        file.write_all(&chunk).await.map_err(internal)?;
+    }
+    file.sync_all().await.map_err(internal)?;
+
+    let saved = persist(tmp, target, state.opts.overwrite)?;
+    info!("Uploaded {} ({} bytes)", saved.display(), written);
+    Ok(saved)
+}
+
+fn persist(
+    mut tmp: NamedTempFile,
+    target: &FsPath,
+    policy: OverwritePolicy,
+) -> Result<PathBuf, UploadError> {
+    match policy {
+        OverwritePolicy::Replace => {
+            tmp.persist(target).map_err(|e| internal(e.error))?;
+            Ok(target.to_path_buf())
+        }
+        OverwritePolicy::Deny => match tmp.persist_noclobber(target) {
+            Ok(_) => Ok(target.to_path_buf()),
+            Err(e) if e.error.kind() == std::io::ErrorKind::AlreadyExists => Err(conflict(target)),
+            Err(e) => Err(internal(e.error)),
+        },
+        OverwritePolicy::Rename => {
+            for candidate in (0..MAX_RENAME_ATTEMPTS).map(|i| numbered_name(target, i)) {
+                match tmp.persist_noclobber(&candidate) {
+                    Ok(_) => return Ok(candidate),
+                    Err(e) if e.error.kind() == std::io::ErrorKind::AlreadyExists => tmp = e.file,
+                    Err(e) => return Err(internal(e.error)),
+                }
+            }
+            Err(conflict(target))
+        }
+    }
+}
+
+/// `a.txt`, `a (1).txt`, `a (2).txt`, ...
+fn numbered_name(target: &FsPath, n: usize) -> PathBuf {
+    if n == 0 {
+        return target.to_path_buf();
+    }
+    let stem = target
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match target.extension() {
+        Some(ext) => format!("{} ({}).{}", stem, n, ext.to_string_lossy()),
+        None => format!("{} ({})", stem, n),
+    };
+    target.with_file_name(name)
+}
+
+fn check_content_length(state: &HttpServerState, headers: &HeaderMap) -> Result<(), UploadError> {
+    let length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    match length {
+        Some(length) if length > state.opts.max_upload_size => Err(too_large(state)),
+        _ => Ok(()),
+    }
+}
+
+fn file_name(path: &FsPath) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn encode_segment(name: &str) -> String {
+    utf8_percent_encode(name, PATH_SEGMENT).to_string()
+}
+
+fn error_response((status, message): UploadError) -> Response<Body> {
+    warn!("Upload failed: {}", message);
+    Response::builder()
+        .status(status)
+        .body(Body::from(message))
+        .unwrap()
+}
+
+fn conflict(target: &FsPath) -> UploadError {
+    (
+        StatusCode::CONFLICT,
+        format!("{} already exists", file_name(target)),
+    )
+}
+
+fn too_large(state: &HttpServerState) -> UploadError {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!(
+            "Upload exceeds the limit of {} bytes",
+            state.opts.max_upload_size
+        ),
+    )
+}
+
+fn bad_request(e: impl Display) -> UploadError {
+    (StatusCode::BAD_REQUEST, e.to_string())
+}
+
+fn internal(e: impl Display) -> UploadError {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbered_name() {
+        let target = FsPath::new("/tmp/a.tar.gz");
+        assert_eq!(numbered_name(target, 0), target);
+        assert_eq!(numbered_name(target, 2), FsPath::new("/tmp/a.tar (2).gz"));
+        assert_eq!(
+            numbered_name(FsPath::new("/tmp/README"), 1),
+            FsPath::new("/tmp/README (1)")
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::body::{to_bytes, Body, HttpBody};
+use axum::extract::State;
+use axum::http::{header, Response, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures_util::{stream, Stream};
+use log::{info, warn};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::{broadcast, watch};
+
+use super::HttpServerState;
+
+/// Path of the Server-Sent Events endpoint browsers listen on.
+pub(super) const RELOAD_PATH: &str = "/__rcli/livereload";
+
+// changes closer together than this trigger a single reload
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+// injected pages are buffered, anything larger is passed through untouched
+const MAX_INJECT_SIZE: usize = 16 << 20;
+
+fn reload_script() -> String {
+    format!(
+        r#"<script>(() => {{
+  const events = new EventSource("{}");
+  events.addEventListener("reload", () => location.reload());
+}})();</script>
+"#,
+        RELOAD_PATH
+    )
+}
+
+/// `--watch` state: the filesystem watcher and the channel reload events are
+/// broadcast on.
+#[derive(Debug)]
+pub(super) struct LiveReload {
+    tx: broadcast::Sender<String>,
+    stop: watch::Sender<bool>,
+    _watcher: RecommendedWatcher,
+}
+
+impl LiveReload {
+    /// Watch `root` recursively. Changes to `ignore`d files, such as an access
+    /// log kept inside the served directory, and to in-progress uploads do not
+    /// trigger reloads.
+    pub fn new(root: &Path, ignore: Vec<PathBuf>) -> Result<Self> {
+        let (tx, _) = broadcast::channel(16);
+        let (changes_tx, changes_rx) = mpsc::channel::<PathBuf>();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if is_change(&event.kind) => {
+                    for path in event.paths {
+                        let _ = changes_tx.send(path);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Watch error: {}", e),
+            })?;
+        watcher.watch(root, RecursiveMode::Recursive)?;
+
+        let reload_tx = tx.clone();
+        let root = root.to_path_buf();
+        // the thread exits once the watcher, and with it `changes_tx`, is dropped
+        std::thread::spawn(move || {
+            while let Ok(path) = changes_rx.recv() {
+                let mut changed = (!is_ignored(&path, &ignore)).then_some(path);
+                loop {
+                    match changes_rx.recv_timeout(DEBOUNCE) {
+                        Ok(path) if !is_ignored(&path, &ignore) => changed = Some(path),
+                        Ok(_) => {}
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                if let Some(path) = changed {
+                    let relative = path.strip_prefix(&root).unwrap_or(&path);
+                    info!("Changed {}, reloading", relative.display());
+                    // no receivers just means no browser is connected
+                    let _ = reload_tx.send(relative.display().to_string());
+                }
+            }
+        });
+
+        Ok(Self {
+            tx,
+            stop: watch::channel(false).0,
+            _watcher: watcher,
+        })
+    }
+
+    /// End all event streams, so open browser tabs do not hold up shutdown.
+    pub fn close(&self) {
+        self.stop.send_replace(true);
+    }
+}
+
+fn is_change(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    )
+}
+
+fn is_ignored(path: &Path, ignore: &[PathBuf]) -> bool {
+    let upload = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with(".upload-"));
+    upload || ignore.iter().any(|p| p == path)
+}
+
+/// `GET /__rcli/livereload`: a `reload` event per batch of changes.
+pub(super) async fn events_handler(
+    State(state): State<Arc<HttpServerState>>,
+) -> Result<Response<Body>, Infallible> {
+    let Some(reload) = &state.live_reload else {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap());
+    };
+    Ok(axum::response::IntoResponse::into_response(
+        Sse::new(reload_events(
+            reload.tx.subscribe(),
+            reload.stop.subscribe(),
+        ))
+        .keep_alive(KeepAlive::default()),
+    ))
+}
+
+fn reload_events(
+    rx: broadcast::Receiver<String>,
+    stop: watch::Receiver<bool>,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold((rx, stop), |(mut rx, mut stop)| async move {
+        let path = tokio::select! {
+            received = rx.recv() => match received {
+                Ok(path) => path,
+                // a slow client missed some changes, one reload covers them all
+                Err(broadcast::error::RecvError::Lagged(_)) => String::new(),
+                Err(broadcast::error::RecvError::Closed) => return None,
+            },
+            _ = stop.wait_for(|stopped| *stopped) => return None,
+        };
+        Some((Ok(Event::default().event("reload").data(path)), (rx, stop)))
+    })
+}
+
+/// Middleware adding the reload script to complete HTML responses.
+pub(super) async fn inject_reload_script(response: Response<Body>) -> Response<Body> {
+    let is_html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+    let fits = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .or_else(|| response.body().size_hint().exact())
+        .is_some_and(|len| len <= MAX_INJECT_SIZE as u64);
+    if response.status() != StatusCode::OK
+        || !is_html
+        || !fits
+        || response.headers().contains_key(header::CONTENT_ENCODING)
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match to_bytes(body, MAX_INJECT_SIZE).await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Error reading page to inject the reload script: {}", e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap();
+        }
+    };
+    let html = inject_script(&body);
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, html.len().into());
+    // the injected page no longer matches byte ranges of the file, nor is it
+    // byte for byte what the file's strong ETag names
+    parts.headers.remove(header::ACCEPT_RANGES);
+    if let Some(etag) = parts
+        .headers
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+    {
+        if !etag.starts_with("W/") {
+            let weak = format!("W/{}", etag);
+            parts.headers.insert(header::ETAG, weak.parse().unwrap());
+        }
+    }
+    Response::from_parts(parts, Body::from(html))
+}
+
+/// Insert the reload script before `</body>`, or append it.
+fn inject_script(html: &[u8]) -> Vec<u8> {
+    let needle = b"</body>";
+    let position = html
+        .windows(needle.len())
+        .rposition(|w| w.eq_ignore_ascii_case(needle))
+        .unwrap_or(html.len());
+    let script = reload_script();
+    let mut injected = Vec::with_capacity(html.len() + script.len());
+    injected.extend_from_slice(&html[..position]);
+    injected.extend_from_slice(script.as_bytes());
+    injected.extend_from_slice(&html[position..]);
+    injected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inject_script() {
+        let html = inject_script(b"<html><BODY>hi</BODY></html>");
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.starts_with("<html><BODY>hi<script>"));
+        assert!(html.ends_with("</script>\n</BODY></html>"));
+        assert!(html.contains(&format!("new EventSource(\"{}\")", RELOAD_PATH)));
+
+        let html = String::from_utf8(inject_script(b"<p>fragment</p>")).unwrap();
+        assert!(html.starts_with("<p>fragment</p><script>"));
+    }
+
+    #[test]
+    fn test_is_ignored() {
+        let ignore = vec![PathBuf::from("/srv/access.log")];
+        assert!(is_ignored(Path::new("/srv/.upload-abc"), &[]));
+        assert!(is_ignored(Path::new("/srv/access.log"), &ignore));
+        assert!(!is_ignored(Path::new("/srv/index.html"), &ignore));
+    }
+}
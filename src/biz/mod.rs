@@ -1,14 +1,18 @@
 pub mod b64;
+pub mod convert;
 pub mod csv_convert;
 pub mod gen_pass;
+pub mod http_client;
 pub mod http_server;
 pub mod jwt;
 pub mod text;
 pub mod text_encrypt;
 
 pub use b64::*;
+pub use convert::*;
 pub use csv_convert::*;
 pub use gen_pass::*;
+pub use http_client::*;
 pub use http_server::*;
 pub use jwt::*;
 pub use text::*;
@@ -1,22 +1,43 @@
+use std::fmt;
+use std::str::FromStr;
+
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
+use tracing::level_filters::LevelFilter;
 
 use crate::{
-    Base64Subcommand, CsvOpts, GenPassOpts, HttpSubCommand, JwtSubCommand, TextSubcommand,
+    Base64Subcommand, ConvertOpts, CsvCommand, GenPassOpts, HttpSubCommand, JwtSubCommand,
+    TextSubcommand, ToCsvOpts,
 };
 
 #[derive(Parser, Debug)]
 #[command(name = "rcli", about, version, author, long_about = None)]
 pub struct Opts {
+    #[arg(
+        long, global = true, default_value = "info", value_parser = parse_log_level, long_help = "Log verbosity: off, error, warn, info, debug or trace. RUST_LOG overrides it per module"
+    )]
+    pub log_level: LogLevel,
     #[clap(subcommand)]
     pub cmd: Subcommand,
 }
 
+// parsed once per run, boxing the large http options buys nothing
+#[allow(clippy::large_enum_variant)]
 #[derive(Parser, Debug)]
 #[enum_dispatch(CmdExec)]
 pub enum Subcommand {
-    #[clap(name = "csv", about = "Convert CSV to File")]
-    Csv(CsvOpts),
+    #[clap(name = "csv", about = "Convert CSV to File, or inspect it")]
+    Csv(CsvCommand),
+    #[clap(
+        name = "to-csv",
+        about = "Convert JSON, YAML, TOML or NDJSON rows to CSV"
+    )]
+    ToCsv(ToCsvOpts),
+    #[clap(
+        name = "convert",
+        about = "Convert between JSON, YAML, TOML, NDJSON and CSV"
+    )]
+    Convert(ConvertOpts),
     #[clap(name = "genpass", about = "Generate password for random")]
     GenPass(GenPassOpts),
     #[command(subcommand, about = "Encode/Decode Base64")]
@@ -28,3 +49,65 @@ pub enum Subcommand {
     #[command(subcommand, about = "JWT")]
     Jwt(JwtSubCommand),
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+fn parse_log_level(s: &str) -> Result<LogLevel, &'static str> {
+    s.parse()
+}
+
+impl From<LogLevel> for &'static str {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => "off",
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::OFF,
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(LogLevel::Off),
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err("Invalid log level"),
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
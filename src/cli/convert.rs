@@ -0,0 +1,36 @@
+use clap::Parser;
+
+use super::csv::parse_file_format;
+use super::verify_file;
+use crate::{convert_file, CmdExec, FileFormat};
+
+#[derive(Parser, Debug)]
+pub struct ConvertOpts {
+    #[arg(short, long, default_value = "-", value_parser = verify_file, long_help = "Input file, - for stdin")]
+    pub input: String,
+    #[arg(short, long, long_help = "Output file, stdout when not given or -")]
+    pub output: Option<String>,
+    #[arg(
+        long, value_parser = parse_file_format,
+        long_help = "Input format: json, yaml, toml, ndjson, csv or tsv; by default from the --input extension"
+    )]
+    pub from: Option<FileFormat>,
+    #[arg(
+        long, value_parser = parse_file_format,
+        long_help = "Output format: json, yaml, toml, ndjson, csv, tsv, md or html; by default from the --output extension"
+    )]
+    pub to: Option<FileFormat>,
+    #[arg(
+        long,
+        long_help = "Write JSON on a single line instead of pretty printing it"
+    )]
+    pub compact: bool,
+    #[arg(long, long_help = "Sort object keys instead of keeping their order")]
+    pub sort_keys: bool,
+}
+
+impl CmdExec for ConvertOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        convert_file(self)
+    }
+}
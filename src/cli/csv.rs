@@ -1,39 +1,265 @@
 use super::verify_file;
-use crate::{csv2file, CmdExec};
-use clap::Parser;
+use crate::{csv2file, csv_schema, csv_stats, file2csv, CmdExec, ColumnType, RowFilter};
+use clap::{ArgAction, Args, Parser};
 use colored::Colorize;
+use enum_dispatch::enum_dispatch;
 use std::fmt;
+use std::fs;
 use std::str::FromStr;
 
 #[derive(Debug, Clone, Copy)]
 pub enum FileFormat {
     Json,
     Yaml,
+    Toml,
+    Ndjson,
+    Csv,
+    Tsv,
+    Markdown,
+    Html,
+}
+
+/// `rcli csv -i FILE ...` converts, `rcli csv stats|schema FILE` inspects.
+#[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct CsvCommand {
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubcommand>,
+    #[command(flatten)]
+    pub convert: Option<CsvOpts>,
+}
+
+#[derive(Parser, Debug)]
+#[enum_dispatch(CmdExec)]
+pub enum CsvSubcommand {
+    #[command(
+        about = "Per column type, nulls, distinct values, min/max/mean, top values and max length"
+    )]
+    Stats(CsvStatsOpts),
+    #[command(about = "JSON Schema of the rows")]
+    Schema(CsvSchemaOpts),
 }
 
 #[derive(Parser, Debug)]
 pub struct CsvOpts {
-    #[arg(short, long, value_parser = verify_file)]
+    // the flattened reader options leave the derived group empty, `input` alone
+    // tells `CsvCommand` there is a conversion
+    #[arg(
+        short, long, group = "CsvOpts", value_parser = verify_file,
+        long_help = "Input CSV file, - for stdin"
+    )]
     pub input: String,
-    #[arg(short, long)]
+    #[arg(
+        short,
+        long,
+        long_help = "Output file, - for stdout; output.<format> by default"
+    )]
     pub output: Option<String>,
-    #[arg(long, default_value = "json", value_parser = parse_file_format)]
-    pub format: FileFormat,
-    #[arg(short, long, default_value = ",")]
-    pub delimiter: char,
-    #[arg(short = 'r', long = "show header", default_value_t = true)]
+    #[arg(
+        long, value_parser = parse_file_format,
+        long_help = "json, yaml, toml, ndjson, csv, tsv, md or html; by default from the --output extension, else json"
+    )]
+    pub format: Option<FileFormat>,
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+    #[arg(
+        long,
+        default_value_t = 1000,
+        long_help = "Infer column types from this many first records; later values that do not fit stay strings"
+    )]
+    pub infer_rows: usize,
+    #[arg(long, long_help = "Also print the converted output")]
+    pub print: bool,
+    #[arg(
+        long = "where", value_name = "EXPR", value_parser = parse_row_filter,
+        long_help = "Keep the rows matching a condition, e.g. \"Age > 30 and Position == 'Goalkeeper'\". \
+                     Compare columns (`quoted` with backticks when they hold spaces) and values with \
+                     == != < <= > >= or contains, combined with and, or, not and parentheses"
+    )]
+    pub filter: Option<RowFilter>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        long_help = "Keep only these columns, in this order, e.g. Name,Age"
+    )]
+    pub select: Vec<String>,
+    #[arg(long, value_delimiter = ',', long_help = "Drop these columns")]
+    pub exclude: Vec<String>,
+    #[arg(
+        long, value_name = "OLD=NEW", value_parser = parse_rename,
+        long_help = "Rename a column in the output. May be repeated"
+    )]
+    pub rename: Vec<(String, String)>,
+    #[arg(
+        long,
+        long_help = "Drop rows equal to an earlier one, after --select and --exclude"
+    )]
+    pub distinct: bool,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        long_help = "Sort the rows by these columns, holding them all in memory; empty values go last"
+    )]
+    pub sort_by: Vec<String>,
+    #[arg(long, requires = "sort_by", long_help = "Sort in descending order")]
+    pub desc: bool,
+    #[arg(long, default_value_t = 0, long_help = "Skip this many rows")]
+    pub offset: usize,
+    #[arg(long, long_help = "Write at most this many rows")]
+    pub limit: Option<usize>,
+}
+
+/// How records are read and typed, shared by `csv` and its subcommands.
+#[derive(Args, Debug)]
+pub struct CsvReadOpts {
+    #[arg(
+        short, long, value_parser = parse_csv_char,
+        long_help = "Field delimiter, detected among , tab ; and | when not given"
+    )]
+    pub delimiter: Option<u8>,
+    #[arg(
+        short = 'r', long, default_value_t = true, action = ArgAction::Set,
+        long_help = "Whether the first row holds the column names, otherwise they are col1..colN"
+    )]
     pub header: bool,
+    #[arg(long, default_value = "\"", value_parser = parse_csv_char, long_help = "Quote character")]
+    pub quote: u8,
+    #[arg(
+        long, value_parser = parse_csv_char,
+        long_help = "Escape character for quotes inside quoted fields, e.g. \\, besides doubling them"
+    )]
+    pub escape: Option<u8>,
+    #[arg(long, value_parser = parse_csv_char, long_help = "Skip lines starting with this character")]
+    pub comment: Option<u8>,
+    #[arg(long, long_help = "Allow records with differing numbers of fields")]
+    pub flexible: bool,
+    #[arg(
+        long,
+        long_help = "Keep every value a string instead of inferring column types"
+    )]
+    pub no_infer: bool,
+    #[arg(
+        long,
+        long_help = "Also infer dates, written as ISO 8601 (2024-01-31, 2024-01-31T08:00:00)"
+    )]
+    pub infer_dates: bool,
+    #[arg(
+        long = "type", value_name = "COLUMN=TYPE", value_parser = parse_column_type,
+        long_help = "Set the type of a column: string, int, float, bool or date. May be repeated"
+    )]
+    pub types: Vec<(String, ColumnType)>,
+}
+
+#[derive(Parser, Debug)]
+pub struct CsvStatsOpts {
+    #[arg(value_parser = verify_file, long_help = "CSV file, - for stdin")]
+    pub input: String,
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+    #[arg(
+        long,
+        default_value_t = 3,
+        long_help = "How many of the most frequent values to show"
+    )]
+    pub top: usize,
+    #[arg(
+        long, default_value = "md", value_parser = parse_file_format,
+        long_help = "json, yaml, toml, ndjson, csv, tsv, md or html"
+    )]
+    pub format: FileFormat,
 }
 
-fn parse_file_format(s: &str) -> Result<FileFormat, &'static str> {
+#[derive(Parser, Debug)]
+pub struct CsvSchemaOpts {
+    #[arg(value_parser = verify_file, long_help = "CSV file, - for stdin")]
+    pub input: String,
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+    #[arg(short, long, long_help = "Output file, stdout by default")]
+    pub output: Option<String>,
+}
+
+pub(super) fn parse_file_format(s: &str) -> Result<FileFormat, &'static str> {
     s.parse()
 }
 
+#[derive(Parser, Debug)]
+pub struct ToCsvOpts {
+    #[arg(short, long, value_parser = verify_file, long_help = "Input file, - for stdin with --format")]
+    pub input: String,
+    #[arg(short, long, long_help = "Output file, output.csv by default")]
+    pub output: Option<String>,
+    #[arg(
+        long, value_parser = parse_file_format,
+        long_help = "Input format: json, yaml, toml, ndjson, csv or tsv; by default from the --input extension"
+    )]
+    pub format: Option<FileFormat>,
+    #[arg(short, long, default_value = ",", value_parser = parse_csv_char)]
+    pub delimiter: u8,
+    #[arg(
+        long, default_value = "join", value_parser = parse_array_mode,
+        long_help = "join the items of arrays into one cell, or explode them into a row each"
+    )]
+    pub arrays: ArrayMode,
+    #[arg(
+        long,
+        default_value = "|",
+        long_help = "Separator of joined array items"
+    )]
+    pub separator: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrayMode {
+    Join,
+    Explode,
+}
+
+fn parse_array_mode(s: &str) -> Result<ArrayMode, &'static str> {
+    match s.to_lowercase().as_str() {
+        "join" => Ok(ArrayMode::Join),
+        "explode" => Ok(ArrayMode::Explode),
+        _ => Err("Expected join or explode"),
+    }
+}
+
+fn parse_csv_char(s: &str) -> Result<u8, &'static str> {
+    match s {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+        _ => Err("Expected a single ASCII character"),
+    }
+}
+
+fn parse_column_type(s: &str) -> Result<(String, ColumnType), &'static str> {
+    let (column, ty) = s.rsplit_once('=').ok_or("Expected COLUMN=TYPE")?;
+    Ok((column.to_string(), ty.parse()?))
+}
+
+fn parse_row_filter(s: &str) -> Result<RowFilter, String> {
+    s.parse()
+}
+
+fn parse_rename(s: &str) -> Result<(String, String), &'static str> {
+    match s.split_once('=') {
+        Some((old, new)) if !old.is_empty() && !new.is_empty() => {
+            Ok((old.to_string(), new.to_string()))
+        }
+        _ => Err("Expected OLD=NEW"),
+    }
+}
+
 impl From<FileFormat> for &'static str {
     fn from(f: FileFormat) -> Self {
         match f {
             FileFormat::Json => "json",
             FileFormat::Yaml => "yaml",
+            FileFormat::Toml => "toml",
+            FileFormat::Ndjson => "ndjson",
+            FileFormat::Csv => "csv",
+            FileFormat::Tsv => "tsv",
+            FileFormat::Markdown => "md",
+            FileFormat::Html => "html",
         }
     }
 }
@@ -45,6 +271,12 @@ impl FromStr for FileFormat {
         match s.to_lowercase().as_str() {
             "json" => Ok(FileFormat::Json),
             "yaml" | "yml" => Ok(FileFormat::Yaml),
+            "toml" => Ok(FileFormat::Toml),
+            "ndjson" | "jsonl" => Ok(FileFormat::Ndjson),
+            "csv" => Ok(FileFormat::Csv),
+            "tsv" | "tab" => Ok(FileFormat::Tsv),
+            "md" | "markdown" => Ok(FileFormat::Markdown),
+            "html" | "htm" => Ok(FileFormat::Html),
             _ => Err("Invalid file format"),
         }
     }
@@ -56,17 +288,58 @@ impl fmt::Display for FileFormat {
     }
 }
 
+impl CmdExec for ToCsvOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let rows = file2csv(self)?;
+        println!("{} {}", "Rows: ".blue(), rows);
+        Ok(())
+    }
+}
+
+impl CmdExec for CsvCommand {
+    async fn execute(self) -> anyhow::Result<()> {
+        match (self.cmd, self.convert) {
+            (Some(cmd), _) => cmd.execute().await,
+            (None, Some(opts)) => opts.execute().await,
+            (None, None) => Err(anyhow::anyhow!("Missing --input or a subcommand")),
+        }
+    }
+}
+
+impl CmdExec for CsvStatsOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        print!("{}", csv_stats(&self)?);
+        Ok(())
+    }
+}
+
+impl CmdExec for CsvSchemaOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let schema = serde_json::to_string_pretty(&csv_schema(&self)?)? + "\n";
+        match self.output.as_deref() {
+            None | Some("-") => print!("{}", schema),
+            Some(path) => fs::write(path, schema)?,
+        }
+        Ok(())
+    }
+}
+
 impl CmdExec for CsvOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        println!("{} {}", "Convert CSV to JSON".blue(), "format: json".blue());
+        // keep stdout for the data when it is the output
+        let to_stdout = self.output.as_deref() == Some("-");
         match csv2file(self) {
-            Ok(output) => {
-                println!("{} {}", "Output: ".blue(), output.blue());
-                println!("{}", "Success Convert CSV to JSON".blue());
+            Ok(rows) if to_stdout => {
+                eprintln!("{} {}", "Rows: ".blue(), rows);
+                Ok(())
+            }
+            Ok(rows) => {
+                println!("{} {}", "Rows: ".blue(), rows);
+                println!("{}", "Success Convert CSV".blue());
                 Ok(())
             }
             Err(e) => {
-                eprintln!("{} {}", "Error: {}".red(), e);
+                eprintln!("{} {}", "Error: ".red(), e);
                 Err(e)
             }
         }
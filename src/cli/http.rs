@@ -1,31 +1,719 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
+use axum::http::Method;
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
 
-use crate::{http_server, CmdExec};
+use crate::{http_mock, http_request, http_server, CmdExec};
 
-use super::verify_path;
+use super::{parse_std_duration, verify_file, verify_path, verify_size};
 
+// parsed once per run, like `Subcommand`
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Parser)]
 #[enum_dispatch(CmdExec)]
 pub enum HttpSubCommand {
     #[command(about = "Serve a directory over HTTP")]
     Serve(HttpServeOpts),
+    #[command(about = "Serve canned responses declared in a route file")]
+    Mock(HttpMockOpts),
+    #[command(about = "Send the requests of a .http file and check their responses")]
+    Request(HttpRequestOpts),
 }
 
 #[derive(Debug, Parser)]
 pub struct HttpServeOpts {
-    #[arg(short, long, default_value = "8080", long_help = "The port to bind to")]
+    #[arg(
+        short,
+        long,
+        default_value = "8080",
+        long_help = "The port to bind to, unless --bind names one"
+    )]
     pub port: u16,
+    #[arg(
+        short, long, default_value = "0.0.0.0", value_parser = parse_bind_addr, long_help = "Address to listen on: an IPv4/IPv6 address or hostname with an optional port, or unix:/path/to.sock. May be repeated"
+    )]
+    pub bind: Vec<BindAddr>,
     #[arg(
         short, long, default_value = ".", value_parser = verify_path, long_help = "The directory to serve"
     )]
     pub dir: PathBuf,
+    #[arg(
+        long,
+        default_value_t = false,
+        long_help = "Follow symbolic links, even when they point outside the served directory"
+    )]
+    pub follow_symlinks: bool,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "index.html,index.htm",
+        long_help = "Files served for a directory instead of its listing, in order of preference"
+    )]
+    pub index: Vec<String>,
+    #[arg(
+        long,
+        default_value_t = false,
+        long_help = "Answer 404 for directories without an index file instead of listing them"
+    )]
+    pub no_listing: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        long_help = "Serve foo.br, foo.zst or foo.gz next to foo to clients accepting that encoding"
+    )]
+    pub precompressed: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        long_help = "Single page app mode: serve the root index file for unknown paths without a file extension"
+    )]
+    pub spa: bool,
+    #[arg(
+        long, value_parser = verify_file, long_help = "Page returned with 404 responses"
+    )]
+    pub not_found: Option<String>,
+    #[arg(
+        long,
+        default_value_t = false,
+        long_help = "Watch the served directory and reload open HTML pages when files change"
+    )]
+    pub watch: bool,
+    #[arg(
+        long, value_parser = parse_proxy_route, long_help = "Forward requests under a path prefix to an upstream, e.g. /api=http://127.0.0.1:3000. May be repeated"
+    )]
+    pub proxy: Vec<ProxyRoute>,
+    #[arg(
+        long, default_value = "5s", value_parser = parse_std_duration, long_help = "How long to wait for a connection to a --proxy upstream"
+    )]
+    pub proxy_connect_timeout: Duration,
+    #[arg(
+        long, default_value = "60s", value_parser = parse_std_duration, long_help = "How long to wait for a --proxy upstream to start responding"
+    )]
+    pub proxy_timeout: Duration,
+    #[arg(
+        long, value_parser = parse_credential, long_help = "Require HTTP basic auth with this user:password. May be repeated"
+    )]
+    pub auth: Vec<Credential>,
+    #[arg(
+        long, value_parser = verify_file, long_help = "htpasswd file of user:hash lines, with bcrypt or argon2 hashes"
+    )]
+    pub auth_file: Option<String>,
+    #[arg(
+        long,
+        default_value = "rcli",
+        long_help = "Realm announced in basic auth challenges"
+    )]
+    pub auth_realm: String,
+    #[arg(
+        long, value_delimiter = ',', value_parser = parse_cidr, long_help = "Only accept clients from these addresses or CIDR ranges, e.g. 10.0.0.0/8,::1"
+    )]
+    pub allow: Vec<Cidr>,
+    #[arg(
+        long, value_delimiter = ',', value_parser = parse_cidr, long_help = "Reject clients from these addresses or CIDR ranges, checked before --allow"
+    )]
+    pub deny: Vec<Cidr>,
+    #[arg(
+        long,
+        default_value_t = false,
+        long_help = "Accept file uploads via PUT and multipart POST"
+    )]
+    pub upload: bool,
+    #[arg(
+        long, default_value = "1G", value_parser = verify_size, long_help = "Maximum size of an uploaded file, or of all files in one multipart request, e.g. 512M"
+    )]
+    pub max_upload_size: u64,
+    #[arg(
+        long, default_value = "deny", value_parser = parse_overwrite_policy, long_help = "What to do when an uploaded file already exists: deny, replace or rename"
+    )]
+    pub overwrite: OverwritePolicy,
+    #[arg(
+        long, requires = "tls_key", conflicts_with = "tls_self_signed", value_parser = verify_file, long_help = "PEM certificate chain to serve HTTPS with"
+    )]
+    pub tls_cert: Option<String>,
+    #[arg(
+        long, requires = "tls_cert", value_parser = verify_file, long_help = "PEM private key matching --tls-cert"
+    )]
+    pub tls_key: Option<String>,
+    #[arg(
+        long,
+        default_value_t = false,
+        long_help = "Serve HTTPS with an ephemeral self-signed certificate"
+    )]
+    pub tls_self_signed: bool,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "localhost,127.0.0.1,::1",
+        long_help = "Subject alternative names of the --tls-self-signed certificate"
+    )]
+    pub tls_san: Vec<String>,
+    #[arg(
+        long, default_value = "30s", value_parser = parse_std_duration, long_help = "How long to wait for in-flight requests when shutting down"
+    )]
+    pub shutdown_timeout: Duration,
+    #[arg(
+        long, value_parser = parse_std_duration, long_help = "Stop the server after no request has been in flight for this long, e.g. 10m; idle keep-alive connections do not count"
+    )]
+    pub idle_exit: Option<Duration>,
+    #[arg(
+        long,
+        long_help = "Serve /healthz and /metrics on this port, on the --bind hosts, instead of next to the files"
+    )]
+    pub admin_port: Option<u16>,
+    #[arg(
+        long,
+        long_help = "Append an access log line per request to this file, - for stdout"
+    )]
+    pub access_log: Option<String>,
+    #[arg(
+        long, default_value = "combined", value_parser = parse_access_log_format, long_help = "Access log format: common, combined or json"
+    )]
+    pub access_log_format: AccessLogFormat,
+    #[arg(
+        long,
+        default_value_t = false,
+        long_help = "Answer cross-origin requests, see --cors-origins"
+    )]
+    pub cors: bool,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "*",
+        long_help = "Origins allowed by --cors, * for any"
+    )]
+    pub cors_origins: Vec<String>,
+    #[arg(
+        long, value_delimiter = ',', default_value = "GET,HEAD,OPTIONS", value_parser = parse_method, long_help = "Methods allowed by --cors"
+    )]
+    pub cors_methods: Vec<Method>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "*",
+        long_help = "Request headers allowed by --cors, * for any"
+    )]
+    pub cors_headers: Vec<String>,
+    #[arg(
+        long,
+        default_value_t = false,
+        long_help = "Do not compress responses, even when the client accepts gzip, br or zstd"
+    )]
+    pub no_compression: bool,
 }
 
 impl CmdExec for HttpServeOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        http_server(self.dir.clone(), self.port).await
+        http_server(self).await
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct HttpMockOpts {
+    #[arg(
+        long, value_parser = verify_file, long_help = "YAML or JSON file with a `routes` list; each route has a path such as /users/:id, and optionally method, status, headers, body or body_file, and delay"
+    )]
+    pub routes: String,
+    #[arg(
+        short,
+        long,
+        default_value = "8080",
+        long_help = "The port to bind to, unless --bind names one"
+    )]
+    pub port: u16,
+    #[arg(
+        short, long, default_value = "0.0.0.0", value_parser = parse_bind_addr, long_help = "Address to listen on: an IPv4/IPv6 address or hostname with an optional port, or unix:/path/to.sock. May be repeated"
+    )]
+    pub bind: Vec<BindAddr>,
+    #[arg(
+        long,
+        long_help = "Append every request received as a JSON line to this file, - for stdout"
+    )]
+    pub record: Option<String>,
+}
+
+impl CmdExec for HttpMockOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        http_mock(self).await
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct HttpRequestOpts {
+    #[arg(value_parser = verify_file, long_help = "A .http file in the JetBrains / VS Code REST Client format")]
+    pub file: String,
+    #[arg(
+        short,
+        long,
+        long_help = "Environment to take variables from, e.g. dev"
+    )]
+    pub env: Option<String>,
+    #[arg(
+        long, value_parser = verify_file, long_help = "JSON file of environments, by default http-client.env.json next to the .http file and its http-client.private.env.json"
+    )]
+    pub env_file: Option<String>,
+    #[arg(
+        long = "var", value_parser = parse_variable, long_help = "Set a variable, name=value, over the file and environment. May be repeated"
+    )]
+    pub vars: Vec<(String, String)>,
+    #[arg(
+        short,
+        long,
+        long_help = "Only send requests whose @name or ### title contains this"
+    )]
+    pub filter: Option<String>,
+    #[arg(
+        long, default_value = "30s", value_parser = parse_std_duration, long_help = "Timeout of each request"
+    )]
+    pub timeout: Duration,
+    #[arg(
+        short = 'k',
+        long,
+        long_help = "Accept invalid TLS certificates, e.g. from --tls-self-signed"
+    )]
+    pub insecure: bool,
+    #[arg(
+        short,
+        long,
+        long_help = "Print only status lines and assertion results"
+    )]
+    pub quiet: bool,
+}
+
+fn parse_variable(s: &str) -> Result<(String, String), &'static str> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.to_string()))
+        }
+        _ => Err("Expected name=value"),
+    }
+}
+
+impl CmdExec for HttpRequestOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        http_request(self).await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    Deny,
+    Replace,
+    Rename,
+}
+
+fn parse_method(s: &str) -> Result<Method, &'static str> {
+    s.to_uppercase().parse().map_err(|_| "Invalid HTTP method")
+}
+
+fn parse_overwrite_policy(s: &str) -> Result<OverwritePolicy, &'static str> {
+    s.parse()
+}
+
+impl From<OverwritePolicy> for &'static str {
+    fn from(p: OverwritePolicy) -> Self {
+        match p {
+            OverwritePolicy::Deny => "deny",
+            OverwritePolicy::Replace => "replace",
+            OverwritePolicy::Rename => "rename",
+        }
+    }
+}
+
+impl FromStr for OverwritePolicy {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "deny" => Ok(OverwritePolicy::Deny),
+            "replace" => Ok(OverwritePolicy::Replace),
+            "rename" => Ok(OverwritePolicy::Rename),
+            _ => Err("Invalid overwrite policy"),
+        }
+    }
+}
+
+impl fmt::Display for OverwritePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    Common,
+    Combined,
+    Json,
+}
+
+fn parse_access_log_format(s: &str) -> Result<AccessLogFormat, &'static str> {
+    s.parse()
+}
+
+impl From<AccessLogFormat> for &'static str {
+    fn from(f: AccessLogFormat) -> Self {
+        match f {
+            AccessLogFormat::Common => "common",
+            AccessLogFormat::Combined => "combined",
+            AccessLogFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for AccessLogFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "common" | "clf" => Ok(AccessLogFormat::Common),
+            "combined" => Ok(AccessLogFormat::Combined),
+            "json" => Ok(AccessLogFormat::Json),
+            _ => Err("Invalid access log format"),
+        }
+    }
+}
+
+impl fmt::Display for AccessLogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+/// A `--bind` address. A missing TCP port falls back to `--port`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindAddr {
+    Tcp { host: String, port: Option<u16> },
+    Unix(PathBuf),
+}
+
+fn parse_bind_addr(s: &str) -> Result<BindAddr, &'static str> {
+    s.parse()
+}
+
+impl FromStr for BindAddr {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(path) = s.strip_prefix("unix:") {
+            return if path.is_empty() {
+                Err("Missing unix socket path")
+            } else {
+                Ok(BindAddr::Unix(PathBuf::from(path)))
+            };
+        }
+        let tcp = |host: &str, port: Option<u16>| BindAddr::Tcp {
+            host: host.to_string(),
+            port,
+        };
+        // bare addresses, including unbracketed IPv6 such as ::1
+        if let Ok(ip) = s.parse::<IpAddr>() {
+            return Ok(tcp(&ip.to_string(), None));
+        }
+        if let Ok(addr) = s.parse::<SocketAddr>() {
+            return Ok(tcp(&addr.ip().to_string(), Some(addr.port())));
+        }
+        if let Some(ip) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            let ip: IpAddr = ip.parse().map_err(|_| "Invalid IPv6 address")?;
+            return Ok(tcp(&ip.to_string(), None));
+        }
+        let (host, port) = match s.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port.parse().map_err(|_| "Invalid port")?)),
+            None => (s, None),
+        };
+        let valid = !host.is_empty()
+            && host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+        if valid {
+            Ok(tcp(host, port))
+        } else {
+            Err("Invalid bind address")
+        }
+    }
+}
+
+impl fmt::Display for BindAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+            BindAddr::Tcp { host, port } => {
+                let host = if host.contains(':') {
+                    format!("[{}]", host)
+                } else {
+                    host.clone()
+                };
+                match port {
+                    Some(port) => write!(f, "{}:{}", host, port),
+                    None => write!(f, "{}", host),
+                }
+            }
+        }
+    }
+}
+
+/// A `--proxy` route: requests under `prefix` go to `upstream`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyRoute {
+    /// Path prefix without a trailing slash, `""` for everything.
+    pub prefix: String,
+    /// `http://host:port` followed by an optional base path without a
+    /// trailing slash.
+    pub upstream: String,
+}
+
+fn parse_proxy_route(s: &str) -> Result<ProxyRoute, &'static str> {
+    s.parse()
+}
+
+impl ProxyRoute {
+    /// Whether `path` falls under the prefix, on a segment boundary.
+    pub fn matches(&self, path: &str) -> bool {
+        path.strip_prefix(&self.prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+}
+
+impl FromStr for ProxyRoute {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (prefix, upstream) = s.split_once('=').ok_or("Expected PREFIX=URL")?;
+        if !prefix.starts_with('/') {
+            return Err("Proxy prefix must start with /");
+        }
+        let rest = upstream
+            .strip_prefix("http://")
+            .ok_or("Proxy upstream must be an http:// URL")?;
+        let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+        if authority.is_empty() || rest[authority.len()..].contains(['?', '#']) {
+            return Err("Invalid proxy upstream");
+        }
+        let uri: axum::http::Uri = upstream.parse().map_err(|_| "Invalid proxy upstream")?;
+        if uri.authority().is_none() {
+            return Err("Invalid proxy upstream");
+        }
+        Ok(ProxyRoute {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            upstream: upstream.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ProxyRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = if self.prefix.is_empty() {
+            "/"
+        } else {
+            &self.prefix
+        };
+        write!(f, "{}={}", prefix, self.upstream)
+    }
+}
+
+/// A `--auth` user and plaintext password.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credential {
+    pub user: String,
+    pub password: String,
+}
+
+fn parse_credential(s: &str) -> Result<Credential, &'static str> {
+    s.parse()
+}
+
+impl FromStr for Credential {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((user, password)) if !user.is_empty() => Ok(Credential {
+                user: user.to_string(),
+                password: password.to_string(),
+            }),
+            _ => Err("Expected user:password"),
+        }
+    }
+}
+
+// keeps passwords out of logged options
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credential")
+            .field("user", &self.user)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An address range for `--allow` and `--deny`. A bare address is a range
+/// of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+fn parse_cidr(s: &str) -> Result<Cidr, &'static str> {
+    s.parse()
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // clients on a dual-stack socket show up as ::ffff:a.b.c.d
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            ip => ip,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| "Invalid IP address")?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| "Invalid CIDR prefix")?,
+            None => max,
+        };
+        if prefix > max {
+            return Err("CIDR prefix too long");
+        }
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp(host: &str, port: Option<u16>) -> BindAddr {
+        BindAddr::Tcp {
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    #[test]
+    fn test_parse_bind_addr() {
+        assert_eq!("0.0.0.0".parse(), Ok(tcp("0.0.0.0", None)));
+        assert_eq!("127.0.0.1:3000".parse(), Ok(tcp("127.0.0.1", Some(3000))));
+        assert_eq!("::1".parse(), Ok(tcp("::1", None)));
+        assert_eq!("[::]".parse(), Ok(tcp("::", None)));
+        assert_eq!("[::1]:3000".parse(), Ok(tcp("::1", Some(3000))));
+        assert_eq!("localhost".parse(), Ok(tcp("localhost", None)));
+        assert_eq!("my-host.lan:80".parse(), Ok(tcp("my-host.lan", Some(80))));
+        assert_eq!(
+            "unix:/tmp/rcli.sock".parse(),
+            Ok(BindAddr::Unix(PathBuf::from("/tmp/rcli.sock")))
+        );
+        assert!("unix:".parse::<BindAddr>().is_err());
+        assert!("host:port".parse::<BindAddr>().is_err());
+        assert!("[::1".parse::<BindAddr>().is_err());
+        assert!("a b".parse::<BindAddr>().is_err());
+    }
+
+    #[test]
+    fn test_parse_proxy_route() {
+        let route: ProxyRoute = "/api/=http://127.0.0.1:3000/v1/".parse().unwrap();
+        assert_eq!(route.prefix, "/api");
+        assert_eq!(route.upstream, "http://127.0.0.1:3000/v1");
+        assert_eq!(route.to_string(), "/api=http://127.0.0.1:3000/v1");
+        assert!(route.matches("/api"));
+        assert!(route.matches("/api/users"));
+        assert!(!route.matches("/apis"));
+
+        let root: ProxyRoute = "/=http://localhost:8000".parse().unwrap();
+        assert_eq!(root.prefix, "");
+        assert!(root.matches("/anything"));
+        assert_eq!(root.to_string(), "/=http://localhost:8000");
+
+        assert!("/api".parse::<ProxyRoute>().is_err());
+        assert!("api=http://localhost".parse::<ProxyRoute>().is_err());
+        assert!("/api=https://localhost".parse::<ProxyRoute>().is_err());
+        assert!("/api=http://".parse::<ProxyRoute>().is_err());
+        assert!("/api=http://localhost/?x=1".parse::<ProxyRoute>().is_err());
+    }
+
+    #[test]
+    fn test_parse_credential() {
+        let credential: Credential = "admin:s3:cret".parse().unwrap();
+        assert_eq!(credential.user, "admin");
+        assert_eq!(credential.password, "s3:cret");
+        assert!(!format!("{:?}", credential).contains("s3:cret"));
+        assert!(":pass".parse::<Credential>().is_err());
+        assert!("admin".parse::<Credential>().is_err());
+    }
+
+    #[test]
+    fn test_cidr() {
+        let private: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(private.contains("10.1.2.3".parse().unwrap()));
+        assert!(private.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!private.contains("11.0.0.1".parse().unwrap()));
+        assert!(!private.contains("::1".parse().unwrap()));
+
+        let loopback: Cidr = "::1".parse().unwrap();
+        assert_eq!(loopback.to_string(), "::1/128");
+        assert!(loopback.contains("::1".parse().unwrap()));
+
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains("203.0.113.9".parse().unwrap()));
+        let link_local: Cidr = "fe80::/10".parse().unwrap();
+        assert!(link_local.contains("fe80::1".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+        assert!("10.0.0.0/x".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_parse_variable() {
+        assert_eq!(
+            parse_variable("host=localhost:8080"),
+            Ok(("host".to_string(), "localhost:8080".to_string()))
+        );
+        assert_eq!(
+            parse_variable("q=a=b"),
+            Ok(("q".to_string(), "a=b".to_string()))
+        );
+        assert!(parse_variable("=x").is_err());
+        assert!(parse_variable("novalue").is_err());
+    }
+
+    #[test]
+    fn test_display_bind_addr() {
+        assert_eq!(tcp("::1", Some(80)).to_string(), "[::1]:80");
+        assert_eq!(tcp("localhost", None).to_string(), "localhost");
+        assert_eq!(
+            BindAddr::Unix(PathBuf::from("/tmp/a.sock")).to_string(),
+            "unix:/tmp/a.sock"
+        );
     }
 }
@@ -1,10 +1,12 @@
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::Result;
 use duration_str::parse;
 
 pub use b64::*;
 pub use cli::*;
+pub use convert::*;
 pub use csv::*;
 pub use gen_pass::*;
 pub use http::*;
@@ -14,6 +16,7 @@ pub use text_encrypt::*;
 mod b64;
 #[allow(clippy::module_inception)]
 mod cli;
+mod convert;
 mod csv;
 mod gen_pass;
 mod http;
@@ -46,6 +49,28 @@ fn verify_duration(exp: &str) -> Result<String, &'static str> {
     Ok(duration.as_secs().to_string())
 }
 
+fn parse_std_duration(s: &str) -> Result<Duration, &'static str> {
+    parse(s).map_err(|_| "Invalid duration")
+}
+
+fn verify_size(size: &str) -> Result<u64, &'static str> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: u64 = number.parse().map_err(|_| "Invalid size")?;
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => return Err("Invalid size unit"),
+    };
+    number.checked_mul(multiplier).ok_or("Size too large")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -56,4 +81,14 @@ mod tests {
         assert!(verify_file("Cargo.toml").is_ok());
         assert!(verify_file("nonexistent").is_err());
     }
+
+    #[test]
+    fn test_verify_size() {
+        assert_eq!(verify_size("1024"), Ok(1024));
+        assert_eq!(verify_size("512K"), Ok(512 * 1024));
+        assert_eq!(verify_size("10 MiB"), Ok(10 * 1024 * 1024));
+        assert_eq!(verify_size("1G"), Ok(1 << 30));
+        assert!(verify_size("G").is_err());
+        assert!(verify_size("1X").is_err());
+    }
 }
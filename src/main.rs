@@ -1,12 +1,22 @@
 use clap::Parser;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::EnvFilter;
 
 use rcli::CmdExec;
 use rcli::Opts;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
     let opts: Opts = Opts::parse();
+    // RUST_LOG directives take precedence over --log-level; records from the
+    // `log` crate are forwarded to the same subscriber
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::from(opts.log_level).into())
+        .from_env_lossy();
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
     opts.cmd.execute().await?;
     Ok(())
 }